@@ -0,0 +1,31 @@
+#!/bin/sh
+# Runs the multi-threaded example against the working tree and against a
+# baseline revision, so contention changes can be compared on the same
+# machine. Numbers are only meaningful with several cores available.
+#
+# Usage: benches/baseline.sh <baseline-revision> [thread counts...]
+set -eu
+
+if [ $# -lt 1 ]; then
+    echo "usage: $0 <baseline-revision> [thread counts...]" >&2
+    exit 1
+fi
+baseline=$1
+shift
+
+root=$(git rev-parse --show-toplevel)
+worktree=$(mktemp -d)
+trap 'git -C "$root" worktree remove --force "$worktree"' EXIT
+git -C "$root" worktree add --detach --quiet "$worktree" "$baseline"
+# Older revisions may not have the current example.
+cp "$root/examples/multi-threaded.rs" "$worktree/examples/multi-threaded.rs"
+
+echo "cores: $(nproc)"
+for tree in "$worktree" "$root"; do
+    if [ "$tree" = "$root" ]; then
+        echo "== working tree"
+    else
+        echo "== $baseline"
+    fi
+    (cd "$tree" && cargo run --quiet --release --example multi-threaded -- "$@")
+done
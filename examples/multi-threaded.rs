@@ -1,25 +1,72 @@
+//! Allocates from many threads at once and reports how long it took, for
+//! each thread count given as an argument. Without arguments, runs with
+//! powers of two up to the available parallelism and with 256 threads.
+//!
+//! Only uses APIs that predate the lock-free slab ring, so that
+//! `benches/baseline.sh` can run it against older revisions.
+
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
 use rebytes::{Allocator, Buffer};
 
+const ITERATIONS: usize = 100_000;
+
 fn main() {
-    let allocator = Allocator::build()
-        .batch_allocation_size(64 * 1024)
-        .finish()
-        .unwrap();
-    let mut threads = Vec::new();
-    for _ in 0..=256 {
-        threads.push(std::thread::spawn({
-            let allocator = allocator.clone();
-            move || worker_thread(&allocator)
-        }));
+    let mut thread_counts = std::env::args()
+        .skip(1)
+        .map(|arg| arg.parse::<usize>().expect("thread counts must be numbers"))
+        .collect::<Vec<_>>();
+    if thread_counts.is_empty() {
+        let parallelism = thread::available_parallelism().map_or(1, usize::from);
+        thread_counts = (0..)
+            .map(|power| 1 << power)
+            .take_while(|threads| *threads < parallelism)
+            .chain([parallelism, 256])
+            .collect();
+    }
+
+    println!("threads  rebytes      global       slabs");
+    for threads in thread_counts {
+        let allocator = Allocator::build()
+            .batch_allocation_size(64 * 1024)
+            .finish()
+            .unwrap();
+        let slabs = run(&allocator, threads);
+
+        let global = Allocator::build()
+            .maximum_allocation_size(0)
+            .finish()
+            .unwrap();
+        let global = run(&global, threads);
+        println!(
+            "{threads:<8} {:<12} {:<12} {}",
+            format!("{slabs:.2?}"),
+            format!("{global:.2?}"),
+            allocator.statistics().slabs
+        );
     }
+}
+
+fn run(allocator: &Allocator, threads: usize) -> Duration {
+    let start = Instant::now();
+    let threads = (0..threads)
+        .map(|_| {
+            let allocator = allocator.clone();
+            thread::spawn(move || worker_thread(&allocator))
+        })
+        .collect::<Vec<_>>();
 
     for thread in threads {
         thread.join().unwrap();
     }
+    start.elapsed()
 }
 
 fn worker_thread(allocator: &Allocator) {
-    for _ in 0..100000 {
+    for _ in 0..ITERATIONS {
         let _ = Buffer::with_capacity(4096, allocator.clone());
     }
 }
@@ -1,9 +1,16 @@
 use std::{
     alloc::{self, Layout},
-    slice,
+    error::Error,
+    fmt::{self, Display},
+    mem, ptr, slice,
+    sync::Arc,
 };
 
-use crate::slab::Slab;
+use crate::{
+    slab::Slab,
+    slabring::SlabRing,
+    tracking::{Tracker, TrackingHandle},
+};
 
 /// An allocation of memory that may be from an [`Allocator`][crate::Allocator]
 /// or from [`alloc::alloc_zeroed()`].
@@ -17,6 +24,7 @@ pub struct Allocation {
     source: Source,
     bytes: *mut u8,
     size: usize,
+    tracking: Option<TrackingHandle>,
 }
 
 impl Allocation {
@@ -25,32 +33,232 @@ impl Allocation {
             source: Source::Slab { slab },
             bytes,
             size,
+            tracking: None,
+        }
+    }
+
+    /// Returns an allocation of zero bytes. No memory is allocated, and the
+    /// address is dangling but well-aligned and non-null.
+    pub const fn empty() -> Self {
+        Self {
+            source: Source::Empty,
+            bytes: ptr::NonNull::dangling().as_ptr(),
+            size: 0,
+            tracking: None,
         }
     }
 
-    /// Returns a new allocation using [`alloc::alloc_zeroed()`].
+    /// Returns a new allocation using [`alloc::alloc_zeroed()`]. If `size` is
+    /// 0, no memory is allocated.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` exceeds `isize::MAX`. If the global allocator fails to
+    /// allocate the memory, [`alloc::handle_alloc_error()`] is called.
     pub fn global(size: usize) -> Self {
-        let layout = Layout::array::<u8>(size).expect("invalid allocation length");
+        Self::try_global(size).unwrap_or_else(|err| handle_alloc_error(err))
+    }
+
+    /// Returns a new allocation using [`alloc::alloc_zeroed()`]. If `size` is
+    /// 0, no memory is allocated.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AllocError::CapacityOverflow`] if `size` exceeds
+    /// `isize::MAX`, and [`AllocError::OutOfMemory`] if the global allocator
+    /// fails to allocate the memory.
+    pub fn try_global(size: usize) -> Result<Self, AllocError> {
+        if size == 0 {
+            return Ok(Self::empty());
+        }
+        let layout = Layout::array::<u8>(size).map_err(|_| AllocError::CapacityOverflow)?;
         // SAFETY: This pointer is freed in Drop. when source is Global.
         let bytes = unsafe { alloc::alloc_zeroed(layout) };
-        Self {
-            source: Source::Global { layout },
+        if bytes.is_null() {
+            return Err(AllocError::OutOfMemory { layout });
+        }
+        Ok(Self {
+            source: Source::Global { layout, ring: None },
             bytes,
             size,
+            tracking: None,
+        })
+    }
+
+    /// Returns an allocation that takes ownership of the memory allocated by
+    /// `vec`. Any spare capacity of `vec` is zeroed.
+    pub(crate) fn from_vec(mut vec: Vec<u8>) -> Self {
+        debug_assert!(vec.capacity() > 0);
+        vec.resize(vec.capacity(), 0);
+        let mut vec = mem::ManuallyDrop::new(vec);
+        let size = vec.len();
+        Self {
+            source: Source::Global {
+                layout: Layout::array::<u8>(size).expect("invalid allocation length"),
+                ring: None,
+            },
+            bytes: vec.as_mut_ptr(),
+            size,
+            tracking: None,
+        }
+    }
+
+    /// Accounts for this allocation in `ring` until it is dropped. Only
+    /// allocations from the global allocator are accounted for this way.
+    pub(crate) fn account(mut self, accounting: SlabRing) -> Self {
+        if let Source::Global { ring, .. } = &mut self.source {
+            *ring = Some(accounting);
+        }
+        self
+    }
+
+    /// Records this allocation in `tracker` until it is dropped.
+    pub(crate) fn track(mut self, tracker: &Arc<Tracker>) -> Self {
+        let slab = match &self.source {
+            Source::Slab { slab } => Some(slab.id()),
+            Source::Global { .. } => None,
+            Source::Empty => return self,
+        };
+        self.tracking = Some(tracker.track(self.size, slab));
+        self
+    }
+
+    fn size_changed(&self) {
+        if let Some(tracking) = &self.tracking {
+            tracking.resize(self.size);
+        }
+    }
+
+    /// Converts this allocation into a `Vec<u8>` containing its first
+    /// `length` bytes without copying. Only allocations from the global
+    /// allocator can be converted.
+    pub(crate) fn into_vec(mut self, length: usize) -> Result<Vec<u8>, Self> {
+        assert!(length <= self.size);
+        match &mut self.source {
+            Source::Global { ring, .. } => {
+                // The memory is no longer owned by an allocator.
+                if let Some(ring) = ring.take() {
+                    ring.release_global(self.size);
+                }
+                self.tracking = None;
+                let this = mem::ManuallyDrop::new(self);
+                // SAFETY: When source is global, bytes came from the global
+                // allocator using `Layout::array::<u8>(size)`, which is the
+                // layout a `Vec<u8>` with a capacity of `size` uses. All bytes
+                // are initialized, and the allocation will not be freed by
+                // Drop.
+                Ok(unsafe { Vec::from_raw_parts(this.bytes, length, this.size) })
+            }
+            Source::Empty => Ok(Vec::new()),
+            Source::Slab { .. } => Err(self),
         }
     }
 
+    /// Returns the address of the first byte of this allocation.
     #[must_use]
     pub const fn address(&self) -> *mut u8 {
         self.bytes
     }
 
+    /// Returns the number of bytes in this allocation.
     #[allow(clippy::len_without_is_empty)]
     #[must_use]
     pub const fn len(&self) -> usize {
         self.size
     }
 
+    /// Attempts to grow this allocation to at least `new_size` bytes without
+    /// moving it. Returns true if this allocation is now at least `new_size`
+    /// bytes long.
+    ///
+    /// Slab allocations can grow when the memory directly following them in
+    /// the slab is free. Allocations from [size class](crate::SlabLayout)
+    /// slabs and from the global allocator never grow in place.
+    pub fn try_grow_in_place(&mut self, new_size: usize) -> bool {
+        if new_size <= self.size {
+            return true;
+        }
+
+        match &self.source {
+            Source::Slab { slab } => {
+                if let Some(size) = slab.grow(self.bytes, self.size, new_size) {
+                    self.size = size;
+                    self.size_changed();
+                    true
+                } else {
+                    false
+                }
+            }
+            Source::Global { .. } | Source::Empty => false,
+        }
+    }
+
+    /// Shrinks this allocation to at least `new_size` bytes, releasing the
+    /// unused memory at the end of this allocation.
+    ///
+    /// Slab allocations never move: the stripes that are no longer needed are
+    /// returned to the slab. Allocations from [size class](crate::SlabLayout)
+    /// slabs are left unchanged. Allocations from the global allocator are
+    /// reallocated, which may move the allocation. A global allocation is left
+    /// unchanged if `new_size` is 0 or if reallocation fails.
+    pub fn shrink_to(&mut self, new_size: usize) {
+        if new_size >= self.size {
+            return;
+        }
+
+        match &mut self.source {
+            Source::Slab { slab } => {
+                self.size = slab.shrink(self.bytes, self.size, new_size);
+            }
+            Source::Global { layout, ring } => {
+                if new_size == 0 {
+                    return;
+                }
+                let Ok(new_layout) = Layout::array::<u8>(new_size) else {
+                    return;
+                };
+                // SAFETY: When source is global, bytes came from alloc() using
+                // layout. new_size is non-zero and smaller than the current
+                // size, so it cannot overflow isize.
+                let bytes = unsafe { alloc::realloc(self.bytes, *layout, new_size) };
+                if !bytes.is_null() {
+                    if let Some(ring) = ring {
+                        ring.release_global_bytes(self.size - new_size);
+                    }
+                    self.bytes = bytes;
+                    self.size = new_size;
+                    *layout = new_layout;
+                }
+            }
+            Source::Empty => return,
+        }
+        self.size_changed();
+    }
+
+    /// Releases up to `length` bytes from the start of this allocation,
+    /// returning the number of bytes released. The address of this allocation
+    /// advances by the number of bytes released.
+    ///
+    /// Only whole stripes of slab allocations are released, and at least one
+    /// stripe is always retained. Allocations from
+    /// [size class](crate::SlabLayout) slabs and from the global allocator
+    /// never release memory from their start.
+    pub fn release_front(&mut self, length: usize) -> usize {
+        match &self.source {
+            Source::Slab { slab } => {
+                let released = slab.release_front(self.bytes, self.size, length);
+                // SAFETY: released is less than size, so the resulting pointer
+                // is still within this allocation.
+                self.bytes = unsafe { self.bytes.add(released) };
+                self.size -= released;
+                self.size_changed();
+                released
+            }
+            Source::Global { .. } | Source::Empty => 0,
+        }
+    }
+
+    /// Returns the allocated memory as a slice.
     #[must_use]
     pub fn as_slice(&self) -> &[u8] {
         // SAFETY: The length and address of this area of memory have been
@@ -60,6 +268,7 @@ impl Allocation {
         unsafe { slice::from_raw_parts(self.address(), self.size) }
     }
 
+    /// Returns the allocated memory as a mutable slice.
     #[must_use]
     pub fn as_slice_mut(&mut self) -> &mut [u8] {
         // SAFETY: The length and address of this area of memory have been
@@ -73,11 +282,15 @@ impl Allocation {
 impl Drop for Allocation {
     fn drop(&mut self) {
         match &self.source {
-            Source::Slab { slab } => slab.free(self.bytes, self.size),
-            Source::Global { layout } => {
+            Source::Slab { slab } => slab.release(self.bytes, self.size),
+            Source::Global { layout, ring } => {
                 // SAFETY: When source is global, bytes came from alloc() not a shared slab.
                 unsafe { alloc::dealloc(self.bytes, *layout) }
+                if let Some(ring) = ring {
+                    ring.release_global(self.size);
+                }
             }
+            Source::Empty => {}
         }
     }
 }
@@ -88,8 +301,93 @@ unsafe impl Send for Allocation {}
 // SAFETY: u8 is Sync, and data is always initialized.
 unsafe impl Sync for Allocation {}
 
+/// An error returned when memory cannot be allocated.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum AllocError {
+    /// The requested length exceeds `isize::MAX` bytes.
+    CapacityOverflow,
+    /// The global allocator could not allocate memory with this layout.
+    OutOfMemory {
+        /// The layout that could not be allocated.
+        layout: Layout,
+    },
+    /// The allocation cannot be served without exceeding the allocator's
+    /// memory limit, and its [`LimitPolicy`](crate::LimitPolicy) does not
+    /// allow falling back to the global allocator.
+    MemoryLimitExceeded {
+        /// The configured memory limit.
+        limit: usize,
+    },
+}
+
+impl Display for AllocError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::CapacityOverflow => f.write_str("capacity overflow"),
+            Self::OutOfMemory { layout } => {
+                write!(f, "memory allocation of {} bytes failed", layout.size())
+            }
+            Self::MemoryLimitExceeded { limit } => {
+                write!(f, "memory limit of {limit} bytes exceeded")
+            }
+        }
+    }
+}
+
+impl Error for AllocError {}
+
+/// Handles an allocation failure in an infallible API.
+///
+/// Capacity overflows and exceeded memory limits panic, while out of memory
+/// errors call [`alloc::handle_alloc_error()`].
+pub(crate) fn handle_alloc_error(error: AllocError) -> ! {
+    match error {
+        AllocError::CapacityOverflow => panic!("capacity overflow"),
+        AllocError::OutOfMemory { layout } => alloc::handle_alloc_error(layout),
+        AllocError::MemoryLimitExceeded { .. } => panic!("{error}"),
+    }
+}
+
 #[derive(Debug)]
 enum Source {
-    Slab { slab: Slab },
-    Global { layout: Layout },
+    Slab {
+        slab: Slab,
+    },
+    Global {
+        layout: Layout,
+        /// The slab ring accounting for this allocation, if any.
+        ring: Option<SlabRing>,
+    },
+    Empty,
+}
+
+#[test]
+fn zero_length_allocations() {
+    use crate::{tracking::AllocationTracking, Allocator, Buffer};
+
+    let global = Allocation::global(0);
+    assert_eq!(global.len(), 0);
+    assert!(!global.address().is_null());
+    assert!(global.as_slice().is_empty());
+    assert_eq!(global.into_vec(0).unwrap().capacity(), 0);
+
+    let allocator = Allocator::build()
+        .allocation_tracking(AllocationTracking::Enabled)
+        .finish()
+        .unwrap();
+    let mut empty = allocator.allocate(0);
+    assert!(matches!(empty.source, Source::Empty));
+    assert!(!empty.try_grow_in_place(1));
+    assert_eq!(empty.release_front(16), 0);
+    assert!(allocator.leak_report().is_empty());
+
+    let global_only = Allocator::build()
+        .maximum_allocation_size(0)
+        .finish()
+        .unwrap();
+    let mut buffer = Buffer::with_capacity(0, global_only);
+    assert_eq!(buffer.as_slice(), b"");
+    buffer.reserve_capacity(0);
+    buffer.extend_from_slice(b"hello");
+    assert_eq!(buffer, b"hello");
 }
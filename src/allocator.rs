@@ -1,23 +1,224 @@
-use std::alloc::{self};
+use std::{
+    alloc::{self},
+    error::Error,
+    fmt::{self, Debug, Display},
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
-use crate::{allocation::Allocation, slabring::SlabRing};
+use crate::{
+    allocation::{handle_alloc_error, AllocError, Allocation},
+    placement::{BestFit, PlacementStrategy},
+    slabring::{LimitPolicy, LimitScope, SlabRing},
+    statistics::Statistics,
+    tracking::{AllocationTracking, LeakReport, OutstandingAllocation, Tracker},
+    GrowthPolicy, ScrubPolicy, SlabLayout,
+};
 
+/// An allocator that hands out [`Allocation`]s from larger, reusable slabs of
+/// memory.
+///
+/// Cloning an allocator is cheap: all clones share the same slabs.
 #[derive(Debug, Clone)]
 pub struct Allocator {
     slabs: SlabRing,
+    tracker: Option<Arc<Tracker>>,
 }
 
 impl Allocator {
+    /// Returns a [`Config`] that can be used to customize and create a new
+    /// allocator.
     pub fn build() -> Config {
         Config::default()
     }
 
+    /// Allocates at least `length` bytes. If the allocation cannot be served
+    /// from a slab, the global allocator is used instead unless the
+    /// [`LimitPolicy`] enforces the memory limit.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `length` exceeds `isize::MAX`. If memory cannot be
+    /// allocated, the configured [out of memory
+    /// hook](Config::out_of_memory_hook) is invoked before calling
+    /// [`alloc::handle_alloc_error()`].
     pub fn allocate(&self, length: usize) -> Allocation {
-        if let Some(allocation) = self.slabs.allocate(length) {
-            allocation
-        } else {
-            Allocation::global(length)
+        self.try_allocate(length)
+            .unwrap_or_else(|err| self.out_of_memory(err))
+    }
+
+    /// Allocates at least `length` bytes. If the allocation cannot be served
+    /// from a slab, the global allocator is used instead unless the
+    /// [`LimitPolicy`] enforces the memory limit. Zero-length
+    /// allocations do not allocate any memory.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`AllocError`] if the memory cannot be allocated.
+    pub fn try_allocate(&self, length: usize) -> Result<Allocation, AllocError> {
+        if length == 0 {
+            return Ok(Allocation::empty());
+        }
+        let allocation = self.slabs.allocate(length)?;
+        Ok(self.track(allocation))
+    }
+
+    /// Allocates at least `length` bytes, blocking the current thread until
+    /// other allocations are freed if the memory limit has been reached.
+    /// Waits at most `timeout`, or indefinitely if `timeout` is `None`.
+    ///
+    /// This function waits regardless of the configured [`LimitPolicy`]. When
+    /// no memory limit is configured, it never waits.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AllocError::MemoryLimitExceeded`] if the timeout elapses or
+    /// if `length` can never be allocated within the memory limit. Returns
+    /// other [`AllocError`]s if the memory cannot be allocated.
+    pub fn allocate_blocking(
+        &self,
+        length: usize,
+        timeout: Option<Duration>,
+    ) -> Result<Allocation, AllocError> {
+        if length == 0 {
+            return Ok(Allocation::empty());
         }
+        let deadline = timeout.and_then(|timeout| Instant::now().checked_add(timeout));
+        let allocation = self
+            .slabs
+            .allocate_with(length, LimitPolicy::Block, deadline)?;
+        Ok(self.track(allocation))
+    }
+
+    /// Allocates at least `length` bytes, waiting until other allocations are
+    /// freed if the memory limit has been reached.
+    ///
+    /// The returned future does not depend on any particular async runtime:
+    /// it is woken when memory allocated by this allocator is freed.
+    /// Like [`Allocator::allocate_blocking()`], it waits regardless of the
+    /// configured [`LimitPolicy`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AllocError::MemoryLimitExceeded`] if `length` can never be
+    /// allocated within the memory limit. Returns other [`AllocError`]s if the
+    /// memory cannot be allocated.
+    pub async fn allocate_async(&self, length: usize) -> Result<Allocation, AllocError> {
+        if length == 0 {
+            return Ok(Allocation::empty());
+        }
+        let allocation = self.slabs.allocate_async(length).await?;
+        Ok(self.track(allocation))
+    }
+
+    fn track(&self, allocation: Allocation) -> Allocation {
+        match &self.tracker {
+            Some(tracker) => allocation.track(tracker),
+            None => allocation,
+        }
+    }
+
+    /// Invokes the out of memory hook, if configured, and then handles
+    /// `error`.
+    pub(crate) fn out_of_memory(&self, error: AllocError) -> ! {
+        if let Some(hook) = &self.slabs.config().out_of_memory_hook {
+            (hook.0)(&error);
+        }
+        handle_alloc_error(error)
+    }
+
+    /// Returns the allocations made by this allocator that have not been
+    /// freed, ordered by their creation.
+    ///
+    /// Returns an empty list unless this allocator was configured with
+    /// [`Config::allocation_tracking()`].
+    #[must_use]
+    pub fn outstanding_allocations(&self) -> Vec<OutstandingAllocation> {
+        self.tracker
+            .as_ref()
+            .map_or_else(Vec::new, |tracker| tracker.outstanding_allocations())
+    }
+
+    /// Returns a report of the allocations made by this allocator that have
+    /// not been freed.
+    ///
+    /// The report is always empty unless this allocator was configured with
+    /// [`Config::allocation_tracking()`].
+    #[must_use]
+    pub fn leak_report(&self) -> LeakReport {
+        LeakReport {
+            allocations: self.outstanding_allocations(),
+        }
+    }
+
+    /// Returns the current memory limit.
+    #[must_use]
+    pub fn memory_limit(&self) -> Option<usize> {
+        self.slabs.memory_limit()
+    }
+
+    /// Changes the memory limit, or removes it if `memory_limit` is `None`.
+    ///
+    /// Lowering the limit below the memory currently in use does not free any
+    /// memory: slabs are never released while the allocator is alive, and
+    /// existing allocations are unaffected. New slabs and, depending on the
+    /// [`LimitScope`], global allocations are refused until usage is below
+    /// the new limit. Raising the limit wakes allocations waiting for memory.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ConfigError::MemoryLimitBelowSlabSize`] if `memory_limit` is
+    /// smaller than the current slab size.
+    pub fn set_memory_limit(&self, memory_limit: Option<usize>) -> Result<(), ConfigError> {
+        self.slabs
+            .reconfigure(|config| config.memory_limit = memory_limit)
+    }
+
+    /// Changes the size of slabs allocated from now on. Existing slabs keep
+    /// their size.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`ConfigError`] if the configuration with the new slab size
+    /// is invalid.
+    pub fn set_slab_size(&self, slab_size: usize) -> Result<(), ConfigError> {
+        self.slabs
+            .reconfigure(|config| config.slab_size = slab_size)
+    }
+
+    /// Changes the size at which allocations are made using the global
+    /// allocator instead of a slab. Existing allocations are unaffected.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ConfigError::MaximumAllocationExceedsSlabSize`] if
+    /// `maximum_allocation_size` exceeds the current slab size.
+    pub fn set_maximum_allocation_size(
+        &self,
+        maximum_allocation_size: usize,
+    ) -> Result<(), ConfigError> {
+        self.slabs
+            .reconfigure(|config| config.maximum_allocation_size = maximum_allocation_size)
+    }
+
+    /// Returns the freed allocations kept in the current thread's cache to
+    /// their slabs. Does nothing unless
+    /// [`Config::thread_cache_capacity()`] is set.
+    pub fn flush_thread_cache(&self) {
+        self.slabs.flush_thread_cache();
+    }
+
+    /// Returns statistics about the memory used by this allocator.
+    #[must_use]
+    pub fn statistics(&self) -> Statistics {
+        self.slabs.statistics()
+    }
+
+    /// Returns the policy [`Buffer`](crate::Buffer)s using this allocator
+    /// follow when they need to grow.
+    #[must_use]
+    pub fn growth_policy(&self) -> GrowthPolicy {
+        self.slabs.config().growth_policy
     }
 }
 
@@ -27,13 +228,41 @@ impl Default for Allocator {
     }
 }
 
+/// Configuration for an [`Allocator`].
 #[derive(Debug, Clone)]
 #[must_use]
 pub struct Config {
+    /// The smallest amount of memory an allocation can occupy within a slab.
+    /// All slab allocations are rounded up to a multiple of this size.
     pub minimum_allocation_size: usize,
+    /// Allocations larger than this size are made using the global allocator.
     pub maximum_allocation_size: usize,
+    /// The maximum amount of memory to dedicate to slabs, or to slabs and
+    /// global allocations depending on `limit_scope`.
     pub memory_limit: Option<usize>,
+    /// Controls what happens when an allocation cannot be served without
+    /// exceeding `memory_limit`.
+    pub limit_policy: LimitPolicy,
+    /// Controls which memory counts towards `memory_limit`.
+    pub limit_scope: LimitScope,
+    /// The size of each slab.
     pub slab_size: usize,
+    /// The growth policy of buffers that use this allocator.
+    pub growth_policy: GrowthPolicy,
+    /// Controls how slabs are divided into allocations.
+    pub slab_layout: SlabLayout,
+    /// Chooses where allocations are placed within
+    /// [mixed](SlabLayout::Mixed) slabs.
+    pub placement_strategy: Arc<dyn PlacementStrategy>,
+    /// Controls whether slab memory is zeroed when it is reused.
+    pub scrub_policy: ScrubPolicy,
+    /// Controls whether live allocations are recorded.
+    pub allocation_tracking: AllocationTracking,
+    /// The number of freed allocations of each size class each thread keeps
+    /// for reuse. 0 disables the thread caches.
+    pub thread_cache_capacity: usize,
+    /// Invoked when an infallible allocation fails.
+    pub out_of_memory_hook: Option<OutOfMemoryHook>,
 }
 
 impl Default for Config {
@@ -42,35 +271,753 @@ impl Default for Config {
             minimum_allocation_size: 16,
             maximum_allocation_size: 16 * 1024,
             memory_limit: None,
+            limit_policy: LimitPolicy::default(),
+            limit_scope: LimitScope::default(),
             slab_size: 256 * 1024,
+            growth_policy: GrowthPolicy::default(),
+            slab_layout: SlabLayout::default(),
+            placement_strategy: Arc::new(BestFit),
+            scrub_policy: ScrubPolicy::default(),
+            allocation_tracking: AllocationTracking::default(),
+            thread_cache_capacity: 0,
+            out_of_memory_hook: None,
         }
     }
 }
 
 impl Config {
+    /// Sets the largest allocation that will be served from a slab.
     pub fn maximum_allocation_size(mut self, maximum_allocation_size: usize) -> Self {
         self.maximum_allocation_size = maximum_allocation_size;
         self
     }
+
+    /// Sets the smallest amount of memory an allocation can occupy within a
+    /// slab.
     pub fn minimum_allocation_size(mut self, minimum_allocation_size: usize) -> Self {
         self.minimum_allocation_size = minimum_allocation_size;
         self
     }
+
+    /// Limits the total amount of memory dedicated to slabs.
+    ///
+    /// By default, allocations that cannot be served once the limit is reached
+    /// use the global allocator. Use [`Config::limit_policy()`] to enforce the
+    /// limit instead, and [`Config::limit_scope()`] to also count allocations
+    /// made using the global allocator towards the limit.
     pub fn memory_limit(mut self, memory_limit: usize) -> Self {
         self.memory_limit = Some(memory_limit);
         self
     }
+
+    /// Sets what happens when an allocation cannot be served without
+    /// exceeding the [memory limit](Config::memory_limit).
+    pub fn limit_policy(mut self, limit_policy: LimitPolicy) -> Self {
+        self.limit_policy = limit_policy;
+        self
+    }
+
+    /// Sets which memory counts towards the
+    /// [memory limit](Config::memory_limit).
+    pub fn limit_scope(mut self, limit_scope: LimitScope) -> Self {
+        self.limit_scope = limit_scope;
+        self
+    }
+
+    /// Sets the size of each slab.
     pub fn batch_allocation_size(mut self, batch_allocation_size: usize) -> Self {
         self.slab_size = batch_allocation_size;
         self
     }
 
-    pub fn finish(mut self) -> Result<Allocator, alloc::LayoutError> {
-        if self.slab_size < self.maximum_allocation_size {
-            self.maximum_allocation_size = self.slab_size;
-        }
+    /// Sets the policy buffers using this allocator follow when they need to
+    /// grow.
+    pub fn growth_policy(mut self, growth_policy: GrowthPolicy) -> Self {
+        self.growth_policy = growth_policy;
+        self
+    }
+
+    /// Sets how slabs are divided into allocations.
+    ///
+    /// [`SlabLayout::SizeClasses`] suits workloads that predominantly
+    /// allocate a few fixed sizes. Slabs are never freed, so memory dedicated
+    /// to one size class cannot be used by allocations of another.
+    pub fn slab_layout(mut self, slab_layout: SlabLayout) -> Self {
+        self.slab_layout = slab_layout;
+        self
+    }
+
+    /// Sets the strategy that chooses where allocations are placed within
+    /// [mixed](SlabLayout::Mixed) slabs. Defaults to [`BestFit`].
+    pub fn placement_strategy<S: PlacementStrategy>(mut self, placement_strategy: S) -> Self {
+        self.placement_strategy = Arc::new(placement_strategy);
+        self
+    }
+
+    /// Sets whether slab memory is zeroed when it is reused.
+    ///
+    /// Use [`ScrubPolicy::ZeroOnFree`] or [`ScrubPolicy::ZeroOnAllocate`] to
+    /// guarantee that an allocation never observes the contents of a previous
+    /// allocation.
+    pub fn scrub_policy(mut self, scrub_policy: ScrubPolicy) -> Self {
+        self.scrub_policy = scrub_policy;
+        self
+    }
+
+    /// Sets whether live allocations are recorded, enabling
+    /// [`Allocator::outstanding_allocations()`] and
+    /// [`Allocator::leak_report()`].
+    ///
+    /// Tracking adds overhead to every allocation and is intended for tests
+    /// and debugging.
+    pub fn allocation_tracking(mut self, allocation_tracking: AllocationTracking) -> Self {
+        self.allocation_tracking = allocation_tracking;
+        self
+    }
+
+    /// Sets the number of freed slab allocations of each size class that each
+    /// thread keeps for reuse. Each thread caches at most one slab's worth of
+    /// memory per allocator.
+    ///
+    /// Allocations served from a thread's cache avoid the locks shared with
+    /// other threads. Cached memory is returned to its slab when the cache
+    /// overflows, when [`Allocator::flush_thread_cache()`] is called, or when
+    /// the thread exits. Until then, it is not available to other threads and
+    /// is not counted as free in [`Allocator::statistics()`].
+    ///
+    /// Disabled by default. Cannot be combined with a
+    /// [memory limit](Config::memory_limit).
+    pub fn thread_cache_capacity(mut self, thread_cache_capacity: usize) -> Self {
+        self.thread_cache_capacity = thread_cache_capacity;
+        self
+    }
+
+    /// Sets a function to invoke when an infallible allocation, such as
+    /// [`Allocator::allocate()`] or [`Buffer::push()`](crate::Buffer::push),
+    /// fails. After the hook returns, [`alloc::handle_alloc_error()`] is
+    /// called.
+    ///
+    /// The fallible APIs, such as [`Allocator::try_allocate()`], return an
+    /// [`AllocError`] without invoking the hook.
+    pub fn out_of_memory_hook<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&AllocError) + Send + Sync + 'static,
+    {
+        self.out_of_memory_hook = Some(OutOfMemoryHook(Arc::new(hook)));
+        self
+    }
+
+    /// Returns a new allocator using this configuration.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`ConfigError`] describing the first invalid setting found.
+    pub fn finish(self) -> Result<Allocator, ConfigError> {
+        self.validate()?;
         Ok(Allocator {
+            tracker: Tracker::new(self.allocation_tracking),
             slabs: SlabRing::new(self)?,
         })
     }
+
+    pub(crate) fn validate(&self) -> Result<(), ConfigError> {
+        if self.minimum_allocation_size == 0 {
+            return Err(ConfigError::ZeroMinimumAllocationSize);
+        }
+        if self.slab_size < self.minimum_allocation_size {
+            return Err(ConfigError::SlabSmallerThanMinimumAllocation {
+                slab_size: self.slab_size,
+                minimum_allocation_size: self.minimum_allocation_size,
+            });
+        }
+        if !self.slab_size.is_multiple_of(self.minimum_allocation_size) {
+            return Err(ConfigError::SlabSizeNotMultipleOfMinimumAllocation {
+                slab_size: self.slab_size,
+                minimum_allocation_size: self.minimum_allocation_size,
+            });
+        }
+        if self.maximum_allocation_size > self.slab_size {
+            return Err(ConfigError::MaximumAllocationExceedsSlabSize {
+                maximum_allocation_size: self.maximum_allocation_size,
+                slab_size: self.slab_size,
+            });
+        }
+        if let Some(memory_limit) = self.memory_limit {
+            if memory_limit < self.slab_size {
+                return Err(ConfigError::MemoryLimitBelowSlabSize {
+                    memory_limit,
+                    slab_size: self.slab_size,
+                });
+            }
+            if self.thread_cache_capacity > 0 {
+                return Err(ConfigError::ThreadCacheWithMemoryLimit);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A function invoked when an infallible allocation fails. See
+/// [`Config::out_of_memory_hook()`].
+#[derive(Clone)]
+pub struct OutOfMemoryHook(Arc<dyn Fn(&AllocError) + Send + Sync>);
+
+impl Debug for OutOfMemoryHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("OutOfMemoryHook").finish_non_exhaustive()
+    }
+}
+
+/// An invalid [`Config`].
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ConfigError {
+    /// `minimum_allocation_size` is 0.
+    ZeroMinimumAllocationSize,
+    /// `slab_size` is smaller than `minimum_allocation_size`, so a slab cannot
+    /// hold a single allocation.
+    SlabSmallerThanMinimumAllocation {
+        /// The configured slab size.
+        slab_size: usize,
+        /// The configured minimum allocation size.
+        minimum_allocation_size: usize,
+    },
+    /// `slab_size` is not a multiple of `minimum_allocation_size`, which would
+    /// leave unusable memory at the end of each slab.
+    SlabSizeNotMultipleOfMinimumAllocation {
+        /// The configured slab size.
+        slab_size: usize,
+        /// The configured minimum allocation size.
+        minimum_allocation_size: usize,
+    },
+    /// `maximum_allocation_size` is larger than `slab_size`, so some
+    /// allocations allowed in slabs could never fit in one.
+    MaximumAllocationExceedsSlabSize {
+        /// The configured maximum allocation size.
+        maximum_allocation_size: usize,
+        /// The configured slab size.
+        slab_size: usize,
+    },
+    /// `memory_limit` is smaller than `slab_size`, so no slabs can be created.
+    MemoryLimitBelowSlabSize {
+        /// The configured memory limit.
+        memory_limit: usize,
+        /// The configured slab size.
+        slab_size: usize,
+    },
+    /// `thread_cache_capacity` is not 0 while a `memory_limit` is set.
+    /// Memory cached by one thread cannot be reclaimed by another thread
+    /// waiting for memory to be freed.
+    ThreadCacheWithMemoryLimit,
+    /// A slab of `slab_size` bytes cannot be allocated.
+    Layout(alloc::LayoutError),
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ZeroMinimumAllocationSize => {
+                f.write_str("minimum_allocation_size must be greater than 0")
+            }
+            Self::SlabSmallerThanMinimumAllocation {
+                slab_size,
+                minimum_allocation_size,
+            } => write!(
+                f,
+                "slab_size ({slab_size}) must be at least minimum_allocation_size ({minimum_allocation_size})"
+            ),
+            Self::SlabSizeNotMultipleOfMinimumAllocation {
+                slab_size,
+                minimum_allocation_size,
+            } => write!(
+                f,
+                "slab_size ({slab_size}) must be a multiple of minimum_allocation_size ({minimum_allocation_size})"
+            ),
+            Self::MaximumAllocationExceedsSlabSize {
+                maximum_allocation_size,
+                slab_size,
+            } => write!(
+                f,
+                "maximum_allocation_size ({maximum_allocation_size}) must not exceed slab_size ({slab_size})"
+            ),
+            Self::MemoryLimitBelowSlabSize {
+                memory_limit,
+                slab_size,
+            } => write!(
+                f,
+                "memory_limit ({memory_limit}) must be at least slab_size ({slab_size})"
+            ),
+            Self::ThreadCacheWithMemoryLimit => {
+                f.write_str("thread_cache_capacity must be 0 when a memory_limit is set")
+            }
+            Self::Layout(err) => write!(f, "invalid slab_size: {err}"),
+        }
+    }
+}
+
+impl Error for ConfigError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Layout(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<alloc::LayoutError> for ConfigError {
+    fn from(err: alloc::LayoutError) -> Self {
+        Self::Layout(err)
+    }
+}
+
+#[test]
+fn config_validation() {
+    assert_eq!(
+        Allocator::build()
+            .minimum_allocation_size(0)
+            .finish()
+            .unwrap_err(),
+        ConfigError::ZeroMinimumAllocationSize
+    );
+    assert!(matches!(
+        Allocator::build()
+            .minimum_allocation_size(48)
+            .finish()
+            .unwrap_err(),
+        ConfigError::SlabSizeNotMultipleOfMinimumAllocation { .. }
+    ));
+    assert!(matches!(
+        Allocator::build()
+            .batch_allocation_size(8)
+            .maximum_allocation_size(8)
+            .finish()
+            .unwrap_err(),
+        ConfigError::SlabSmallerThanMinimumAllocation { .. }
+    ));
+    assert!(matches!(
+        Allocator::build()
+            .batch_allocation_size(4096)
+            .finish()
+            .unwrap_err(),
+        ConfigError::MaximumAllocationExceedsSlabSize { .. }
+    ));
+    assert!(matches!(
+        Allocator::build().memory_limit(1024).finish().unwrap_err(),
+        ConfigError::MemoryLimitBelowSlabSize { .. }
+    ));
+    assert!(matches!(
+        Allocator::build()
+            .batch_allocation_size(usize::MAX)
+            .maximum_allocation_size(0)
+            .minimum_allocation_size(1)
+            .finish()
+            .unwrap_err(),
+        ConfigError::Layout(_)
+    ));
+    assert!(matches!(
+        Allocator::build()
+            .memory_limit(1024 * 1024)
+            .thread_cache_capacity(4)
+            .finish()
+            .unwrap_err(),
+        ConfigError::ThreadCacheWithMemoryLimit
+    ));
+    let cached = Allocator::build()
+        .thread_cache_capacity(4)
+        .finish()
+        .unwrap();
+    assert!(matches!(
+        cached.set_memory_limit(Some(1024 * 1024)).unwrap_err(),
+        ConfigError::ThreadCacheWithMemoryLimit
+    ));
+}
+
+#[test]
+fn limit_policies() {
+    use std::{
+        sync::atomic::{AtomicBool, Ordering},
+        thread,
+        time::Duration,
+    };
+
+    let config = Allocator::build()
+        .batch_allocation_size(1024)
+        .maximum_allocation_size(1024)
+        .memory_limit(1024);
+
+    let allocator = config.clone().finish().unwrap();
+    let _full = allocator.allocate(1000);
+    assert_eq!(allocator.allocate(100).len(), 100);
+
+    let allocator = config
+        .clone()
+        .limit_policy(LimitPolicy::Error)
+        .finish()
+        .unwrap();
+    let full = allocator.allocate(1000);
+    assert_eq!(
+        allocator.try_allocate(100).unwrap_err(),
+        AllocError::MemoryLimitExceeded { limit: 1024 }
+    );
+    assert!(allocator.try_allocate(2048).is_err());
+    drop(full);
+    assert!(allocator.try_allocate(100).is_ok());
+
+    let allocator = config.limit_policy(LimitPolicy::Block).finish().unwrap();
+    let full = allocator.allocate(1000);
+    let allocated = Arc::new(AtomicBool::new(false));
+    let waiter = thread::spawn({
+        let allocator = allocator.clone();
+        let allocated = allocated.clone();
+        move || {
+            let allocation = allocator.allocate(100);
+            allocated.store(true, Ordering::SeqCst);
+            allocation.len()
+        }
+    });
+    thread::sleep(Duration::from_millis(50));
+    assert!(!allocated.load(Ordering::SeqCst));
+    drop(full);
+    assert_eq!(waiter.join().unwrap(), 112);
+    assert!(allocated.load(Ordering::SeqCst));
+}
+
+#[test]
+fn waiting_allocations() {
+    use std::{
+        future::Future,
+        pin::pin,
+        sync::atomic::{AtomicBool, Ordering},
+        task::{Context, Poll, Wake, Waker},
+    };
+
+    struct Flag(AtomicBool);
+
+    impl Wake for Flag {
+        fn wake(self: Arc<Self>) {
+            self.0.store(true, Ordering::SeqCst);
+        }
+    }
+
+    let allocator = Allocator::build()
+        .batch_allocation_size(1024)
+        .maximum_allocation_size(1024)
+        .memory_limit(1024)
+        .finish()
+        .unwrap();
+    let full = allocator.allocate(1000);
+    assert_eq!(
+        allocator
+            .allocate_blocking(100, Some(Duration::from_millis(10)))
+            .unwrap_err(),
+        AllocError::MemoryLimitExceeded { limit: 1024 }
+    );
+    assert!(allocator.allocate_blocking(2048, None).is_err());
+
+    let woken = Arc::new(Flag(AtomicBool::new(false)));
+    let waker = Waker::from(woken.clone());
+    let mut cx = Context::from_waker(&waker);
+    let mut future = pin!(allocator.allocate_async(100));
+    assert!(future.as_mut().poll(&mut cx).is_pending());
+    assert!(future.as_mut().poll(&mut cx).is_pending());
+    assert!(!woken.0.load(Ordering::SeqCst));
+
+    drop(full);
+    assert!(woken.0.load(Ordering::SeqCst));
+    let Poll::Ready(allocation) = future.as_mut().poll(&mut cx) else {
+        panic!("allocation was not ready after memory was freed")
+    };
+    assert_eq!(allocation.unwrap().len(), 112);
+    assert_eq!(
+        allocator
+            .allocate_blocking(100, Some(Duration::from_millis(10)))
+            .unwrap()
+            .len(),
+        112
+    );
+
+    // Dropping a waiting future deregisters its waker.
+    let full = allocator.allocate(1000);
+    woken.0.store(false, Ordering::SeqCst);
+    let mut future = Box::pin(allocator.allocate_async(100));
+    assert!(future.as_mut().poll(&mut cx).is_pending());
+    drop(future);
+    drop(full);
+    assert!(!woken.0.load(Ordering::SeqCst));
+}
+
+#[test]
+fn global_accounting() {
+    use crate::Buffer;
+
+    let allocator = Allocator::default();
+    let small = allocator.allocate(100);
+    let mut large = allocator.allocate(32 * 1024);
+    let statistics = allocator.statistics();
+    assert_eq!(statistics.slabs, 1);
+    assert_eq!(statistics.slab_bytes, 256 * 1024);
+    assert!(statistics.free_slab_bytes <= 256 * 1024 - small.len());
+    assert_eq!(statistics.global_allocations, 1);
+    assert_eq!(statistics.global_bytes, 32 * 1024);
+    large.shrink_to(1024);
+    assert_eq!(allocator.statistics().global_bytes, 1024);
+    drop(large);
+    assert_eq!(allocator.statistics().global_bytes, 0);
+    assert_eq!(allocator.statistics().global_allocations, 0);
+
+    let allocator = Allocator::build()
+        .batch_allocation_size(1024)
+        .maximum_allocation_size(1024)
+        .memory_limit(2048)
+        .limit_policy(LimitPolicy::Error)
+        .limit_scope(LimitScope::Total)
+        .finish()
+        .unwrap();
+    let large = allocator.allocate(1500);
+    assert!(allocator.try_allocate(100).is_err());
+    drop(large);
+    let small = allocator.allocate(100);
+    assert!(allocator.try_allocate(1500).is_err());
+    let large = allocator.allocate(1024);
+    assert_eq!(allocator.statistics().total_bytes(), 2048);
+    assert!(allocator.allocate_blocking(4096, None).is_err());
+    drop((small, large));
+
+    let vec = Vec::from(Buffer::with_capacity(1024, allocator.clone()));
+    assert_eq!(vec.capacity(), 1024);
+    assert_eq!(allocator.statistics().global_bytes, 0);
+}
+
+#[test]
+fn reconfiguration() {
+    let allocator = Allocator::build()
+        .batch_allocation_size(1024)
+        .maximum_allocation_size(1024)
+        .memory_limit(1024)
+        .limit_policy(LimitPolicy::Error)
+        .finish()
+        .unwrap();
+    let _first = allocator.allocate(1000);
+    assert!(allocator.try_allocate(100).is_err());
+
+    allocator.set_memory_limit(Some(2048)).unwrap();
+    assert_eq!(allocator.memory_limit(), Some(2048));
+    let _second = allocator.allocate(100);
+    assert_eq!(allocator.statistics().slabs, 2);
+    assert!(matches!(
+        allocator.set_memory_limit(Some(512)).unwrap_err(),
+        ConfigError::MemoryLimitBelowSlabSize { .. }
+    ));
+    assert!(allocator.set_slab_size(4096).is_err());
+
+    allocator.set_memory_limit(None).unwrap();
+    allocator.set_slab_size(4096).unwrap();
+    allocator.set_maximum_allocation_size(4096).unwrap();
+    let third = allocator.allocate(3000);
+    assert_eq!(third.len(), 3008);
+    assert_eq!(allocator.statistics().slab_bytes, 2 * 1024 + 4096);
+    assert!(matches!(
+        allocator.set_maximum_allocation_size(8192).unwrap_err(),
+        ConfigError::MaximumAllocationExceedsSlabSize { .. }
+    ));
+}
+
+#[test]
+fn thread_caches() {
+    let allocator = Allocator::build()
+        .batch_allocation_size(1024)
+        .maximum_allocation_size(1024)
+        .thread_cache_capacity(2)
+        .scrub_policy(ScrubPolicy::ZeroOnAllocate)
+        .finish()
+        .unwrap();
+    let mut first = allocator.allocate(100);
+    first.as_slice_mut().fill(1);
+    let address = first.address();
+    drop(first);
+    // The cached allocation is not returned to its slab.
+    assert!(allocator.statistics().free_slab_bytes < 1024);
+    let reused = allocator.allocate(112);
+    assert_eq!(reused.address(), address);
+    assert!(reused.as_slice().iter().all(|byte| *byte == 0));
+
+    // Allocations of other sizes are not served from the cache.
+    let other = allocator.allocate(200);
+    assert_ne!(other.address(), address);
+    drop((reused, other));
+    allocator.flush_thread_cache();
+    assert_eq!(allocator.statistics().free_slab_bytes, 1024);
+
+    // Overflowing the cache returns the least recently freed half.
+    let allocations = (0..3).map(|_| allocator.allocate(16)).collect::<Vec<_>>();
+    drop(allocations);
+    let free_bytes = allocator.statistics().free_slab_bytes;
+    assert!(free_bytes > 1024 - 3 * 32 && free_bytes < 1024);
+
+    // Exiting a thread returns its cached allocations.
+    std::thread::spawn({
+        let allocator = allocator.clone();
+        move || drop(allocator.allocate(500))
+    })
+    .join()
+    .unwrap();
+    assert_eq!(allocator.statistics().free_slab_bytes, free_bytes);
+
+    // Each thread caches at most one slab's worth of memory.
+    let larger = allocator.allocate(160);
+    let larger_address = larger.address();
+    let too_large = allocator.allocate(880);
+    drop(larger);
+    drop(too_large);
+    let statistics = allocator.statistics();
+    assert_eq!(statistics.slabs, 2);
+    let cached_bytes = statistics.slab_bytes - statistics.free_slab_bytes;
+    assert!((160..880).contains(&cached_bytes));
+
+    // Allocations are cached by size class, so a smaller allocation in the
+    // same class reuses the cached allocation.
+    let reused = allocator.allocate(140);
+    assert_eq!(reused.address(), larger_address);
+    assert_eq!(reused.len(), 160);
+}
+
+#[test]
+fn home_slabs() {
+    let allocator = Allocator::build()
+        .batch_allocation_size(1024)
+        .maximum_allocation_size(1024)
+        .finish()
+        .unwrap();
+    let first = allocator.allocate(1000);
+    let second = allocator.allocate(512);
+    drop(first);
+
+    // Allocations continue from the slab this thread last allocated from,
+    // even when the thread also uses another allocator.
+    let other = Allocator::default();
+    let home = second.address() as usize;
+    for _ in 0..4 {
+        drop(other.allocate(16));
+        let allocation = allocator.allocate(16);
+        let address = allocation.address() as usize;
+        assert!(address > home && address < home + 1024);
+    }
+    let statistics = allocator.statistics();
+    assert_eq!(statistics.slabs, 2);
+    assert_eq!(statistics.contended_slabs, 0);
+}
+
+#[test]
+fn size_class_slabs() {
+    let allocator = Allocator::build()
+        .slab_layout(SlabLayout::SizeClasses)
+        .finish()
+        .unwrap();
+    let packet = allocator.allocate(1500);
+    assert_eq!(packet.len(), 1536);
+    let small = allocator.allocate(100);
+    assert_eq!(small.len(), 112);
+    let other = allocator.allocate(1530);
+    assert_eq!(allocator.statistics().slabs, 2);
+
+    // Freed slots are reused by allocations of the same size class.
+    let address = other.address();
+    drop(other);
+    assert_eq!(allocator.allocate(1510).address(), address);
+    drop((packet, small));
+    let statistics = allocator.statistics();
+    assert_eq!(statistics.free_slab_bytes, statistics.slab_bytes);
+}
+
+#[test]
+fn custom_placement() {
+    use crate::FreeSpan;
+
+    /// Only allocates from the last free span.
+    #[derive(Debug)]
+    struct LastFit;
+
+    impl PlacementStrategy for LastFit {
+        fn select(&self, free_spans: &[FreeSpan], stripes: usize, _cursor: usize) -> Option<usize> {
+            free_spans
+                .len()
+                .checked_sub(1)
+                .filter(|last| free_spans[*last].stripes() >= stripes)
+        }
+    }
+
+    let allocator = Allocator::build()
+        .batch_allocation_size(1024)
+        .maximum_allocation_size(1024)
+        .placement_strategy(LastFit)
+        .finish()
+        .unwrap();
+    let first = allocator.allocate(256);
+    let second = allocator.allocate(256);
+    let first_address = first.address();
+    drop(first);
+    // The free span where the first allocation was is never used.
+    let third = allocator.allocate(16);
+    assert!(third.address() > second.address());
+    assert_ne!(third.address(), first_address);
+}
+
+#[test]
+fn guard_stripes_fit_slabs() {
+    // With debug checks, the guard stripe following an allocation of nearly
+    // the slab size does not fit, so the allocation is made globally instead
+    // of adding slabs that can never serve it.
+    for memory_limit in [None, Some(8192)] {
+        let mut config = Allocator::build()
+            .batch_allocation_size(1024)
+            .maximum_allocation_size(1024);
+        config.memory_limit = memory_limit;
+        let allocator = config.finish().unwrap();
+        let _allocation = allocator.allocate(1020);
+        let statistics = allocator.statistics();
+        if cfg!(feature = "debug-checks") {
+            assert_eq!(statistics.slabs, 0);
+            assert_eq!(statistics.global_allocations, 1);
+        } else {
+            assert_eq!(statistics.slabs, 1);
+        }
+    }
+}
+
+#[test]
+fn thread_cache_follows_slab_size() {
+    let allocator = Allocator::build()
+        .batch_allocation_size(1024)
+        .maximum_allocation_size(1024)
+        .thread_cache_capacity(2)
+        .finish()
+        .unwrap();
+    let allocation = allocator.allocate(600);
+    allocator.set_maximum_allocation_size(512).unwrap();
+    allocator.set_slab_size(512).unwrap();
+    // The allocation is larger than a slab's worth of memory now.
+    drop(allocation);
+    let statistics = allocator.statistics();
+    assert_eq!(statistics.free_slab_bytes, statistics.slab_bytes);
+}
+
+#[test]
+fn removing_limit_wakes_waiters() {
+    use std::thread;
+
+    let allocator = Allocator::build()
+        .batch_allocation_size(1024)
+        .maximum_allocation_size(1024)
+        .memory_limit(1024)
+        .limit_policy(LimitPolicy::Block)
+        .finish()
+        .unwrap();
+    let _full = allocator.allocate(1000);
+    let waiter = thread::spawn({
+        let allocator = allocator.clone();
+        move || allocator.allocate(1000).len()
+    });
+    thread::sleep(Duration::from_millis(50));
+    assert!(!waiter.is_finished());
+    allocator.set_memory_limit(None).unwrap();
+    assert_eq!(waiter.join().unwrap(), 1008);
 }
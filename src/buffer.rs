@@ -1,141 +1,882 @@
 use std::{
     borrow::{Borrow, BorrowMut},
-    io::Write,
-    ops::{Deref, DerefMut},
+    cmp::Ordering,
+    fmt::{self, Debug},
+    hash::{Hash, Hasher},
+    io::{self, Read, Write},
+    mem::MaybeUninit,
+    ops::{Bound, Deref, DerefMut, Range, RangeBounds},
+    slice,
+    sync::Arc,
 };
 
-use crate::{Allocation, Allocator};
+use crate::{
+    allocation::handle_alloc_error, AllocError, Allocation, Allocator, BufferCursor, BufferReader,
+    Bytes, Drain, Splice,
+};
+
+/// The minimum amount of spare capacity [`Buffer::read_to_end_from()`]
+/// reserves before each read.
+const MINIMUM_READ_SIZE: usize = 32;
 
-#[derive(Debug, Default)]
+/// A contiguous, growable buffer of bytes whose memory is allocated from an
+/// [`Allocator`].
+///
+/// A buffer can be split into multiple buffers using [`Buffer::split_off()`]
+/// and [`Buffer::split_to()`] without copying. Each buffer created this way
+/// exclusively owns a distinct region of the same allocation.
+#[derive(Default)]
 pub struct Buffer {
     allocator: Option<Allocator>,
-    allocation: Option<Allocation>,
+    storage: Option<Storage>,
+    offset: usize,
+    capacity: usize,
     length: usize,
+    growth_policy: Option<GrowthPolicy>,
 }
 
 impl Buffer {
+    /// Returns an empty buffer that allocates from `allocator`.
     #[must_use]
     pub const fn new(allocator: Allocator) -> Self {
         Self {
             allocator: Some(allocator),
-            allocation: None,
+            storage: None,
+            offset: 0,
+            capacity: 0,
             length: 0,
+            growth_policy: None,
         }
     }
 
-    fn allocate(&self, length: usize) -> Allocation {
+    fn try_allocate(&self, length: usize) -> Result<Allocation, AllocError> {
         match &self.allocator {
-            Some(allocator) => allocator.allocate(length),
-            None => Allocation::global(length),
+            Some(allocator) => allocator.try_allocate(length),
+            None => Allocation::try_global(length),
         }
     }
 
-    #[must_use]
-    pub fn with_capacity(capacity: usize, allocator: Allocator) -> Self {
+    fn out_of_memory(&self, error: AllocError) -> ! {
+        match &self.allocator {
+            Some(allocator) => allocator.out_of_memory(error),
+            None => handle_alloc_error(error),
+        }
+    }
+
+    fn from_allocation(allocation: Allocation, length: usize, allocator: Allocator) -> Self {
         Self {
-            allocation: Some(allocator.allocate(capacity)),
             allocator: Some(allocator),
-            length: 0,
+            capacity: allocation.len(),
+            storage: Some(Storage::Unique(allocation)),
+            offset: 0,
+            length,
+            growth_policy: None,
         }
     }
+
+    /// Returns an empty buffer that can hold at least `capacity` bytes
+    /// without reallocating.
+    #[must_use]
+    pub fn with_capacity(capacity: usize, allocator: Allocator) -> Self {
+        Self::from_allocation(allocator.allocate(capacity), 0, allocator)
+    }
+
+    /// Returns a buffer containing `length` zeroed bytes.
     #[must_use]
     pub fn with_len(length: usize, allocator: Allocator) -> Self {
+        let mut buffer = Self::from_allocation(allocator.allocate(length), length, allocator);
+        buffer.as_slice_mut().fill(0);
+        buffer
+    }
+
+    /// Returns an empty buffer with the same allocator and growth policy as
+    /// this buffer.
+    fn empty_like(&self) -> Self {
         Self {
-            allocation: Some(allocator.allocate(length)),
-            allocator: Some(allocator),
-            length,
+            allocator: self.allocator.clone(),
+            storage: None,
+            offset: 0,
+            capacity: 0,
+            length: 0,
+            growth_policy: self.growth_policy,
         }
     }
 
+    /// Returns the policy this buffer follows when it needs to grow.
+    ///
+    /// Unless overridden using [`Buffer::set_growth_policy()`], this is the
+    /// [`Allocator`]'s growth policy.
+    #[must_use]
+    pub fn growth_policy(&self) -> GrowthPolicy {
+        self.growth_policy.unwrap_or_else(|| {
+            self.allocator
+                .as_ref()
+                .map_or_else(GrowthPolicy::default, Allocator::growth_policy)
+        })
+    }
+
+    /// Overrides the policy this buffer follows when it needs to grow.
+    pub fn set_growth_policy(&mut self, growth_policy: GrowthPolicy) {
+        self.growth_policy = Some(growth_policy);
+    }
+
+    /// Returns the number of bytes in this buffer.
     #[must_use]
     pub const fn len(&self) -> usize {
         self.length
     }
 
+    /// Sets the length of this buffer, reserving capacity if needed. When the
+    /// buffer grows, the newly exposed bytes are zeroed.
     pub fn set_len(&mut self, new_length: usize) {
+        let length = self.length;
         self.reserve_capacity(new_length);
         self.length = new_length;
+        if new_length > length {
+            self.as_slice_mut()[length..].fill(0);
+        }
+    }
+
+    /// Returns the unused capacity of this buffer.
+    ///
+    /// After writing to the returned slice, [`Buffer::advance_len()`] can be
+    /// used to mark the written bytes as part of this buffer.
+    pub fn spare_capacity_mut(&mut self) -> &mut [MaybeUninit<u8>] {
+        let length = self.length;
+        let spare = &mut self.region_mut()[length..];
+        // SAFETY: MaybeUninit<u8> has the same layout as u8, and the returned
+        // slice borrows this buffer exclusively just as `spare` did.
+        unsafe { slice::from_raw_parts_mut(spare.as_mut_ptr().cast(), spare.len()) }
+    }
+
+    /// Grows the length of this buffer by `additional_bytes`, exposing the
+    /// bytes written through [`Buffer::spare_capacity_mut()`].
+    ///
+    /// # Safety
+    ///
+    /// `additional_bytes` must not exceed the length of the slice returned by
+    /// [`Buffer::spare_capacity_mut()`], and the first `additional_bytes` of
+    /// it must have been initialized.
+    pub unsafe fn advance_len(&mut self, additional_bytes: usize) {
+        debug_assert!(additional_bytes <= self.capacity - self.length);
+        self.length += additional_bytes;
     }
 
+    /// Removes all bytes from this buffer. The capacity is not affected.
     pub fn clear(&mut self) {
         self.length = 0;
     }
 
+    /// Returns true if this buffer contains no bytes.
     #[must_use]
     pub const fn is_empty(&self) -> bool {
         self.length == 0
     }
 
+    /// Returns the number of bytes this buffer can hold without reallocating.
     #[must_use]
-    pub fn capacity(&self) -> usize {
-        self.allocation.as_ref().map_or(0, Allocation::len)
+    pub const fn capacity(&self) -> usize {
+        self.capacity
     }
 
+    /// Returns the contents of this buffer.
     #[must_use]
     pub fn as_slice(&self) -> &[u8] {
-        match &self.allocation {
-            Some(allocation) => &allocation.as_slice()[..self.length],
-            None => &[],
-        }
+        &self.region()[..self.length]
     }
 
+    /// Returns the contents of this buffer as a mutable slice.
     #[must_use]
     pub fn as_slice_mut(&mut self) -> &mut [u8] {
-        // SAFETY: The length and address of this area of memory have been
-        // dedicated to this Allocation instance. The Rust borrow checker will
-        // prevent any other attempt to borrow slices to this range of memory
-        // while this exclusive reference is held.
-        match &mut self.allocation {
-            Some(allocation) => &mut allocation.as_slice_mut()[..self.length],
+        let length = self.length;
+        &mut self.region_mut()[..length]
+    }
+
+    /// Returns this buffer's entire region of its allocation, including the
+    /// unused capacity.
+    pub(crate) fn region(&self) -> &[u8] {
+        match &self.storage {
+            // SAFETY: `offset..offset + capacity` is a region of the
+            // allocation that is dedicated to this buffer.
+            Some(storage) => unsafe {
+                slice::from_raw_parts(
+                    storage.allocation().address().add(self.offset),
+                    self.capacity,
+                )
+            },
+            None => &[],
+        }
+    }
+
+    /// Returns this buffer's entire region of its allocation as a mutable
+    /// slice, including the unused capacity.
+    pub(crate) fn region_mut(&mut self) -> &mut [u8] {
+        match &mut self.storage {
+            // SAFETY: `offset..offset + capacity` is a region of the
+            // allocation that is dedicated to this buffer. When the allocation
+            // is shared, every other buffer or view of it covers a distinct
+            // region. The Rust borrow checker will prevent any other attempt
+            // to borrow slices to this range of memory while this exclusive
+            // reference is held.
+            Some(storage) => unsafe {
+                slice::from_raw_parts_mut(
+                    storage.allocation().address().add(self.offset),
+                    self.capacity,
+                )
+            },
             None => &mut [],
         }
     }
 
+    /// Sets the length of this buffer without reserving capacity. The bytes
+    /// exposed are whatever the region already contains.
+    pub(crate) fn set_len_within_capacity(&mut self, new_length: usize) {
+        debug_assert!(new_length <= self.capacity);
+        self.length = new_length;
+    }
+
+    /// Ensures this buffer's capacity is at least `total_capacity` bytes.
+    ///
+    /// Unlike the operations that append to this buffer, this function does
+    /// not apply the buffer's [`GrowthPolicy`].
     pub fn reserve_capacity(&mut self, total_capacity: usize) {
-        if self.capacity() >= total_capacity {
-            return;
+        if let Err(err) = self.try_reserve_capacity(total_capacity) {
+            self.out_of_memory(err);
+        }
+    }
+
+    /// Ensures this buffer's capacity is at least `total_capacity` bytes.
+    ///
+    /// Unlike the operations that append to this buffer, this function does
+    /// not apply the buffer's [`GrowthPolicy`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`AllocError`] if the memory cannot be allocated. When an
+    /// error occurs, this buffer is unchanged.
+    pub fn try_reserve_capacity(&mut self, total_capacity: usize) -> Result<(), AllocError> {
+        if self.capacity >= total_capacity {
+            return Ok(());
         }
 
-        let mut new_allocation = self.allocate(total_capacity);
+        // When no other buffer shares the allocation, try to claim the memory
+        // following this buffer's region before falling back to allocating
+        // and copying.
+        if let Some(allocation) = self.storage.as_mut().and_then(Storage::unique_mut) {
+            if allocation.try_grow_in_place(self.offset + total_capacity) {
+                self.capacity = allocation.len() - self.offset;
+                return Ok(());
+            }
+        }
+
+        let mut new_allocation = self.try_allocate(total_capacity)?;
         // Copy any existing data
         if self.length > 0 {
             new_allocation.as_slice_mut()[..self.length].copy_from_slice(self.as_slice());
         }
-        self.allocation = Some(new_allocation);
+        self.capacity = new_allocation.len();
+        self.offset = 0;
+        self.storage = Some(Storage::Unique(new_allocation));
+        Ok(())
+    }
+
+    /// Ensures that at least `additional_bytes` can be appended to this buffer,
+    /// growing according to the buffer's [`GrowthPolicy`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`AllocError`] if the required capacity overflows or the
+    /// memory cannot be allocated. When an error occurs, this buffer is
+    /// unchanged.
+    pub fn try_reserve(&mut self, additional_bytes: usize) -> Result<(), AllocError> {
+        self.try_grow_for(additional_bytes)
     }
 
+    /// Grows the capacity of this buffer by `additional_bytes`.
     pub fn extend_capacity_by(&mut self, additional_bytes: usize) {
         self.reserve_capacity(self.capacity() + additional_bytes);
     }
 
+    /// Ensures that at least `additional_bytes` can be appended to this buffer
+    /// without reallocating.
     pub fn preallocate_for(&mut self, additional_bytes: usize) {
         self.reserve_capacity(self.len() + additional_bytes);
     }
 
+    /// Ensures that at least `additional_bytes` can be appended to this buffer,
+    /// growing according to the buffer's [`GrowthPolicy`].
+    fn grow_for(&mut self, additional_bytes: usize) {
+        if let Err(err) = self.try_grow_for(additional_bytes) {
+            self.out_of_memory(err);
+        }
+    }
+
+    fn try_grow_for(&mut self, additional_bytes: usize) -> Result<(), AllocError> {
+        let required_capacity = self
+            .len()
+            .checked_add(additional_bytes)
+            .ok_or(AllocError::CapacityOverflow)?;
+        let capacity = self.capacity();
+        if capacity < required_capacity {
+            self.try_reserve_capacity(self.growth_policy().grow(capacity, required_capacity))?;
+        }
+        Ok(())
+    }
+
+    /// Shrinks the capacity of this buffer as close to its length as possible.
+    ///
+    /// See [`Buffer::shrink_to()`] for details.
+    pub fn shrink_to_fit(&mut self) {
+        self.shrink_to(0);
+    }
+
+    /// Shrinks the capacity of this buffer to hold at least the larger of
+    /// `min_capacity` and the length of this buffer.
+    ///
+    /// When the buffer's memory is from a slab, the unused stripes are
+    /// returned to the slab without moving the contents of this buffer. Memory
+    /// shared with other buffers created by splitting this buffer is not
+    /// released until all of the buffers sharing it are dropped.
+    pub fn shrink_to(&mut self, min_capacity: usize) {
+        let target_capacity = self.length.max(min_capacity);
+        if target_capacity == 0 {
+            self.storage = None;
+            self.offset = 0;
+            self.capacity = 0;
+        } else if let Some(allocation) = self.storage.as_mut().and_then(Storage::unique_mut) {
+            allocation.shrink_to(self.offset + target_capacity);
+            self.capacity = allocation.len() - self.offset;
+        }
+    }
+
+    /// Splits this buffer into two at `at`. This buffer retains the bytes
+    /// `[0, at)`, and the returned buffer contains the bytes `[at, len)`.
+    ///
+    /// No bytes are copied: both buffers share the same allocation, and the
+    /// returned buffer receives this buffer's remaining capacity.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `at > len`.
+    #[must_use]
+    pub fn split_off(&mut self, at: usize) -> Self {
+        assert!(
+            at <= self.length,
+            "`at` split index (is {at}) should be <= len (is {})",
+            self.length
+        );
+        let mut other = self.empty_like();
+        if at < self.capacity {
+            other.storage = self.share();
+            other.offset = self.offset + at;
+            other.capacity = self.capacity - at;
+            other.length = self.length - at;
+        }
+
+        self.capacity = at;
+        self.length = at;
+        if at == 0 {
+            self.storage = None;
+            self.offset = 0;
+        }
+
+        other
+    }
+
+    /// Splits this buffer into two at `at`. The returned buffer contains the
+    /// bytes `[0, at)`, and this buffer retains the bytes `[at, len)`.
+    ///
+    /// No bytes are copied: both buffers share the same allocation, and this
+    /// buffer keeps its remaining capacity.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `at > len`.
+    #[must_use]
+    pub fn split_to(&mut self, at: usize) -> Self {
+        assert!(
+            at <= self.length,
+            "`at` split index (is {at}) should be <= len (is {})",
+            self.length
+        );
+        let mut other = self.empty_like();
+        if at > 0 {
+            other.storage = self.share();
+            other.offset = self.offset;
+            other.capacity = at;
+            other.length = at;
+        }
+
+        self.offset += at;
+        self.capacity -= at;
+        self.length -= at;
+        if self.capacity == 0 {
+            self.storage = None;
+            self.offset = 0;
+        }
+
+        other
+    }
+
+    /// Appends `other` to the end of this buffer.
+    ///
+    /// If `other` was split from this buffer and their regions are still
+    /// contiguous, the buffers are joined without copying. Otherwise, the
+    /// contents of `other` are copied to the end of this buffer.
+    #[allow(clippy::needless_pass_by_value)]
+    pub fn unsplit(&mut self, other: Self) {
+        if let (Some(Storage::Shared(ours)), Some(Storage::Shared(theirs))) =
+            (&self.storage, &other.storage)
+        {
+            if Arc::ptr_eq(ours, theirs)
+                && self.length == self.capacity
+                && self.offset + self.capacity == other.offset
+            {
+                self.capacity += other.capacity;
+                self.length += other.length;
+                return;
+            }
+        }
+
+        self.extend_from_slice(&other);
+    }
+
+    /// Removes the first `amount` bytes from this buffer without copying the
+    /// remaining bytes.
+    ///
+    /// If no other buffer or view shares this buffer's allocation, the stripes
+    /// before the remaining bytes are returned to the slab.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `amount > len`.
+    pub fn consume(&mut self, amount: usize) {
+        assert!(
+            amount <= self.length,
+            "cannot consume {amount} bytes from a buffer of length {}",
+            self.length
+        );
+        self.offset += amount;
+        self.capacity -= amount;
+        self.length -= amount;
+        if self.capacity == 0 {
+            self.storage = None;
+            self.offset = 0;
+        } else if let Some(allocation) = self.storage.as_mut().and_then(Storage::unique_mut) {
+            self.offset -= allocation.release_front(self.offset);
+        }
+    }
+
+    /// Returns a cursor that reads this buffer's contents.
+    #[must_use]
+    pub const fn cursor(&self) -> BufferCursor<&Self> {
+        BufferCursor::new(self)
+    }
+
+    /// Returns a cursor that reads this buffer's contents and owns this
+    /// buffer.
+    #[must_use]
+    pub const fn into_cursor(self) -> BufferCursor<Self> {
+        BufferCursor::new(self)
+    }
+
+    /// Returns a reader that consumes this buffer's contents as they are
+    /// read, releasing memory as it goes.
+    #[must_use]
+    pub const fn into_reader(self) -> BufferReader {
+        BufferReader::new(self)
+    }
+
+    /// Converts this buffer's storage into a shared allocation, returning a
+    /// new reference to it.
+    fn share(&mut self) -> Option<Storage> {
+        let shared = self.storage.take()?.into_shared();
+        self.storage = Some(Storage::Shared(shared.clone()));
+        Some(Storage::Shared(shared))
+    }
+
+    /// Converts this buffer into an immutable, cheaply clonable [`Bytes`].
+    ///
+    /// Any unused capacity is released before the allocation is shared. The
+    /// memory is freed once the last view of it is dropped.
+    #[must_use]
+    pub fn freeze(mut self) -> Bytes {
+        self.shrink_to_fit();
+        Bytes::new(
+            self.storage.take().map(Storage::into_shared),
+            self.offset,
+            self.length,
+        )
+    }
+
+    /// Appends `byte` to the end of this buffer.
     pub fn push(&mut self, byte: u8) {
         if self.length == self.capacity() {
-            self.preallocate_for(1);
+            self.grow_for(1);
+        }
+        let insert_at = self.length;
+        self.length += 1;
+        self.as_slice_mut()[insert_at] = byte;
+    }
+
+    /// Appends `byte` to the end of this buffer.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`AllocError`] if this buffer needed to grow and the memory
+    /// cannot be allocated. When an error occurs, this buffer is unchanged.
+    pub fn try_push(&mut self, byte: u8) -> Result<(), AllocError> {
+        if self.length == self.capacity() {
+            self.try_grow_for(1)?;
         }
         let insert_at = self.length;
         self.length += 1;
         self.as_slice_mut()[insert_at] = byte;
+        Ok(())
     }
 
+    /// Appends all bytes from `bytes` to the end of this buffer.
     pub fn extend<Bytes: IntoIterator<Item = u8>>(&mut self, bytes: Bytes) {
         let bytes = bytes.into_iter();
         let (estimated_size, _) = bytes.size_hint();
-        self.preallocate_for(estimated_size);
+        self.grow_for(estimated_size);
         for byte in bytes {
             self.push(byte);
         }
     }
 
+    /// Appends `bytes` to the end of this buffer.
     pub fn extend_from_slice(&mut self, bytes: &[u8]) {
-        self.preallocate_for(bytes.len());
+        self.grow_for(bytes.len());
+
+        let insert_at = self.length;
+        self.length += bytes.len();
+        self.as_slice_mut()[insert_at..].copy_from_slice(bytes);
+    }
+
+    /// Appends `bytes` to the end of this buffer.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`AllocError`] if this buffer needed to grow and the memory
+    /// cannot be allocated. When an error occurs, this buffer is unchanged.
+    pub fn try_extend_from_slice(&mut self, bytes: &[u8]) -> Result<(), AllocError> {
+        self.try_grow_for(bytes.len())?;
 
         let insert_at = self.length;
         self.length += bytes.len();
         self.as_slice_mut()[insert_at..].copy_from_slice(bytes);
+        Ok(())
+    }
+
+    /// Reads at most `max_bytes` from `reader` directly into this buffer's
+    /// spare capacity using a single call to [`Read::read()`], growing this
+    /// buffer as needed. Returns the number of bytes read.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error returned by `reader`. When an error occurs, this
+    /// buffer's length is unchanged.
+    pub fn read_from<R: Read + ?Sized>(
+        &mut self,
+        reader: &mut R,
+        max_bytes: usize,
+    ) -> io::Result<usize> {
+        self.grow_for(max_bytes);
+        let length = self.length;
+        let bytes_read = reader.read(&mut self.region_mut()[length..length + max_bytes])?;
+        self.length += bytes_read;
+        Ok(bytes_read)
+    }
+
+    /// Reads all bytes from `reader` until it reaches the end of its input,
+    /// appending them to this buffer. Returns the number of bytes read.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error other than [`io::ErrorKind::Interrupted`] returned by
+    /// `reader`. When an error occurs, all bytes read before the error have
+    /// been appended to this buffer.
+    pub fn read_to_end_from<R: Read + ?Sized>(&mut self, reader: &mut R) -> io::Result<usize> {
+        let start = self.length;
+        loop {
+            if self.length == self.capacity {
+                self.grow_for(MINIMUM_READ_SIZE);
+            }
+            let length = self.length;
+            match reader.read(&mut self.region_mut()[length..]) {
+                Ok(0) => return Ok(self.length - start),
+                Ok(bytes_read) => self.length += bytes_read,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Reads exactly `bytes` bytes from `reader`, appending them to this
+    /// buffer.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error returned by [`Read::read_exact()`], including
+    /// [`io::ErrorKind::UnexpectedEof`] if `reader` reaches the end of its
+    /// input first. When an error occurs, this buffer's length is unchanged.
+    pub fn read_exact_from<R: Read + ?Sized>(
+        &mut self,
+        reader: &mut R,
+        bytes: usize,
+    ) -> io::Result<()> {
+        self.grow_for(bytes);
+        let length = self.length;
+        reader.read_exact(&mut self.region_mut()[length..length + bytes])?;
+        self.length += bytes;
+        Ok(())
+    }
+
+    /// Shortens this buffer to `length` bytes. Has no effect if this buffer
+    /// is already shorter than `length`. The capacity is not affected.
+    pub fn truncate(&mut self, length: usize) {
+        if length < self.length {
+            self.length = length;
+        }
+    }
+
+    /// Removes and returns the last byte of this buffer, or `None` if it is
+    /// empty.
+    pub fn pop(&mut self) -> Option<u8> {
+        let new_length = self.length.checked_sub(1)?;
+        let byte = self.as_slice()[new_length];
+        self.length = new_length;
+        Some(byte)
+    }
+
+    /// Inserts `byte` at `index`, shifting all bytes after it to the right.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index > len`.
+    pub fn insert(&mut self, index: usize, byte: u8) {
+        self.insert_from_slice(index, &[byte]);
+    }
+
+    /// Inserts `bytes` at `index`, shifting all bytes after it to the right.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index > len`.
+    pub fn insert_from_slice(&mut self, index: usize, bytes: &[u8]) {
+        let length = self.length;
+        assert!(
+            index <= length,
+            "insertion index (is {index}) should be <= len (is {length})"
+        );
+        self.grow_for(bytes.len());
+        self.length += bytes.len();
+        let contents = self.as_slice_mut();
+        contents.copy_within(index..length, index + bytes.len());
+        contents[index..index + bytes.len()].copy_from_slice(bytes);
+    }
+
+    /// Removes and returns the byte at `index`, shifting all bytes after it to
+    /// the left.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index >= len`.
+    pub fn remove(&mut self, index: usize) -> u8 {
+        let length = self.length;
+        assert!(
+            index < length,
+            "removal index (is {index}) should be < len (is {length})"
+        );
+        let contents = self.as_slice_mut();
+        let byte = contents[index];
+        contents.copy_within(index + 1.., index);
+        self.length -= 1;
+        byte
+    }
+
+    /// Removes and returns the byte at `index`, replacing it with the last
+    /// byte of this buffer. This does not preserve ordering, but is O(1).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index >= len`.
+    pub fn swap_remove(&mut self, index: usize) -> u8 {
+        let length = self.length;
+        assert!(
+            index < length,
+            "swap_remove index (is {index}) should be < len (is {length})"
+        );
+        let contents = self.as_slice_mut();
+        let byte = contents[index];
+        contents[index] = contents[length - 1];
+        self.length -= 1;
+        byte
+    }
+
+    /// Retains only the bytes for which `keep` returns true, preserving their
+    /// order.
+    pub fn retain<F: FnMut(&u8) -> bool>(&mut self, mut keep: F) {
+        let mut retained = 0;
+        let contents = self.as_slice_mut();
+        for index in 0..contents.len() {
+            let byte = contents[index];
+            if keep(&byte) {
+                contents[retained] = byte;
+                retained += 1;
+            }
+        }
+        self.length = retained;
+    }
+
+    /// Removes consecutive repeated bytes.
+    pub fn dedup(&mut self) {
+        if self.length < 2 {
+            return;
+        }
+
+        let mut retained = 1;
+        let contents = self.as_slice_mut();
+        for index in 1..contents.len() {
+            if contents[index] != contents[retained - 1] {
+                contents[retained] = contents[index];
+                retained += 1;
+            }
+        }
+        self.length = retained;
+    }
+
+    /// Resizes this buffer to `new_length` bytes. If this buffer grows, the
+    /// new bytes are set to `value`.
+    pub fn resize(&mut self, new_length: usize, value: u8) {
+        let length = self.length;
+        if new_length > length {
+            self.grow_for(new_length - length);
+            self.length = new_length;
+            self.as_slice_mut()[length..].fill(value);
+        } else {
+            self.length = new_length;
+        }
+    }
+
+    /// Removes the bytes in `range` from this buffer, returning an iterator
+    /// over the removed bytes.
+    ///
+    /// The bytes are removed even if the iterator is not fully consumed. If
+    /// the iterator is leaked, this buffer is truncated to the start of
+    /// `range`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the start of `range` is greater than its end, or if the end
+    /// of `range` is greater than the length of this buffer.
+    pub fn drain<R: RangeBounds<usize>>(&mut self, range: R) -> Drain<'_> {
+        let range = resolve_range(range, self.length);
+        Drain::new(self, range)
+    }
+
+    /// Replaces the bytes in `range` with `replace_with`, returning an
+    /// iterator over the removed bytes.
+    ///
+    /// The replacement happens when the returned iterator is dropped, even if
+    /// it is not fully consumed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the start of `range` is greater than its end, or if the end
+    /// of `range` is greater than the length of this buffer.
+    pub fn splice<R, I>(&mut self, range: R, replace_with: I) -> Splice<'_, I::IntoIter>
+    where
+        R: RangeBounds<usize>,
+        I: IntoIterator<Item = u8>,
+    {
+        Splice::new(self.drain(range), replace_with.into_iter())
+    }
+}
+
+/// Converts `range` into a [`Range`] within `0..length`, panicking with the
+/// same messages as slice indexing.
+pub(crate) fn resolve_range<R: RangeBounds<usize>>(range: R, length: usize) -> Range<usize> {
+    let start = match range.start_bound() {
+        Bound::Included(start) => *start,
+        Bound::Excluded(start) => start
+            .checked_add(1)
+            .expect("attempted to index slice from after maximum usize"),
+        Bound::Unbounded => 0,
+    };
+    let end = match range.end_bound() {
+        Bound::Included(end) => end
+            .checked_add(1)
+            .expect("attempted to index slice up to maximum usize"),
+        Bound::Excluded(end) => *end,
+        Bound::Unbounded => length,
+    };
+    assert!(
+        start <= end,
+        "slice index starts at {start} but ends at {end}"
+    );
+    assert!(
+        end <= length,
+        "range end index {end} out of range for slice of length {length}"
+    );
+    start..end
+}
+
+/// The allocation backing a [`Buffer`].
+#[derive(Debug)]
+enum Storage {
+    /// An allocation owned by a single buffer.
+    Unique(Allocation),
+    /// An allocation whose regions are owned by multiple buffers or views.
+    Shared(Arc<Allocation>),
+}
+
+impl Storage {
+    fn allocation(&self) -> &Allocation {
+        match self {
+            Self::Unique(allocation) => allocation,
+            Self::Shared(allocation) => allocation,
+        }
+    }
+
+    /// Returns the allocation if no other buffer or view shares it.
+    fn unique_mut(&mut self) -> Option<&mut Allocation> {
+        match self {
+            Self::Unique(allocation) => Some(allocation),
+            Self::Shared(allocation) => Arc::get_mut(allocation),
+        }
+    }
+
+    fn into_shared(self) -> Arc<Allocation> {
+        match self {
+            Self::Unique(allocation) => Arc::new(allocation),
+            Self::Shared(allocation) => allocation,
+        }
+    }
+}
+
+/// Controls how a [`Buffer`] grows its capacity when appending bytes.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default)]
+pub enum GrowthPolicy {
+    /// Grow to exactly the capacity required. Appending one byte at a time
+    /// reallocates on every append that exceeds the current capacity.
+    Exact,
+    /// Grow to at least double the current capacity, making appends amortized
+    /// O(1).
+    #[default]
+    Doubling,
+}
+
+impl GrowthPolicy {
+    /// Returns the capacity a buffer with `current_capacity` should grow to
+    /// in order to hold at least `required_capacity` bytes.
+    #[must_use]
+    pub fn grow(self, current_capacity: usize, required_capacity: usize) -> usize {
+        match self {
+            Self::Exact => required_capacity,
+            Self::Doubling => required_capacity.max(current_capacity.saturating_mul(2)),
+        }
     }
 }
 
@@ -177,13 +918,162 @@ impl BorrowMut<[u8]> for Buffer {
     }
 }
 
+impl Clone for Buffer {
+    fn clone(&self) -> Self {
+        let mut clone = self.empty_like();
+        clone.extend_from_slice(self);
+        clone
+    }
+}
+
+impl Debug for Buffer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "b\"{}\"", self.escape_ascii())
+    }
+}
+
+impl PartialEq for Buffer {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_slice() == other.as_slice()
+    }
+}
+
+impl Eq for Buffer {}
+
+macro_rules! impl_partial_eq {
+    ($($other:ty),+ $(,)?) => {
+        $(
+            impl PartialEq<$other> for Buffer {
+                fn eq(&self, other: &$other) -> bool {
+                    self.as_slice() == &other[..]
+                }
+            }
+
+            impl PartialEq<Buffer> for $other {
+                fn eq(&self, other: &Buffer) -> bool {
+                    &self[..] == other.as_slice()
+                }
+            }
+        )+
+    };
+}
+
+impl_partial_eq!([u8], &[u8], Vec<u8>);
+
+impl<const N: usize> PartialEq<[u8; N]> for Buffer {
+    fn eq(&self, other: &[u8; N]) -> bool {
+        self.as_slice() == other
+    }
+}
+
+impl<const N: usize> PartialEq<&[u8; N]> for Buffer {
+    fn eq(&self, other: &&[u8; N]) -> bool {
+        self.as_slice() == *other
+    }
+}
+
+impl<const N: usize> PartialEq<Buffer> for [u8; N] {
+    fn eq(&self, other: &Buffer) -> bool {
+        self == other.as_slice()
+    }
+}
+
+impl PartialOrd for Buffer {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Buffer {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.as_slice().cmp(other.as_slice())
+    }
+}
+
+impl Hash for Buffer {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_slice().hash(state);
+    }
+}
+
+impl From<&[u8]> for Buffer {
+    /// Returns a buffer containing a copy of `bytes` that allocates from the
+    /// global allocator.
+    fn from(bytes: &[u8]) -> Self {
+        let mut buffer = Self::default();
+        buffer.extend_from_slice(bytes);
+        buffer
+    }
+}
+
+impl From<Vec<u8>> for Buffer {
+    /// Returns a buffer that takes ownership of `bytes`' memory without
+    /// copying. The buffer allocates from the global allocator when it needs
+    /// to grow.
+    fn from(bytes: Vec<u8>) -> Self {
+        if bytes.capacity() == 0 {
+            return Self::default();
+        }
+
+        let length = bytes.len();
+        let allocation = Allocation::from_vec(bytes);
+        Self {
+            allocator: None,
+            capacity: allocation.len(),
+            storage: Some(Storage::Unique(allocation)),
+            offset: 0,
+            length,
+            growth_policy: None,
+        }
+    }
+}
+
+impl From<Buffer> for Vec<u8> {
+    /// Converts `buffer` into a `Vec<u8>`. The memory is reused if it was
+    /// allocated by the global allocator, otherwise the contents are copied.
+    fn from(mut buffer: Buffer) -> Self {
+        if buffer.offset == 0 {
+            if let Some(Storage::Unique(allocation)) = buffer.storage.take() {
+                match allocation.into_vec(buffer.length) {
+                    Ok(vec) => return vec,
+                    Err(allocation) => buffer.storage = Some(Storage::Unique(allocation)),
+                }
+            }
+        }
+
+        buffer.as_slice().to_vec()
+    }
+}
+
+impl Extend<u8> for Buffer {
+    fn extend<T: IntoIterator<Item = u8>>(&mut self, iter: T) {
+        Self::extend(self, iter);
+    }
+}
+
+impl<'a> Extend<&'a u8> for Buffer {
+    fn extend<T: IntoIterator<Item = &'a u8>>(&mut self, iter: T) {
+        Self::extend(self, iter.into_iter().copied());
+    }
+}
+
+impl FromIterator<u8> for Buffer {
+    /// Collects the bytes into a buffer that allocates from the global
+    /// allocator.
+    fn from_iter<T: IntoIterator<Item = u8>>(iter: T) -> Self {
+        let mut buffer = Self::default();
+        buffer.extend(iter);
+        buffer
+    }
+}
+
 impl Write for Buffer {
-    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
         self.extend_from_slice(buf);
         Ok(buf.len())
     }
 
-    fn flush(&mut self) -> std::io::Result<()> {
+    fn flush(&mut self) -> io::Result<()> {
         Ok(())
     }
 }
@@ -209,3 +1099,284 @@ fn basic_tests() {
     buffer.extend_from_slice(b", world!");
     assert_eq!(buffer.as_slice(), b"hello, world!");
 }
+
+#[test]
+fn growth_tests() {
+    let allocator = Allocator::build()
+        .minimum_allocation_size(4)
+        .finish()
+        .unwrap();
+    let mut buffer = Buffer::new(allocator.clone());
+    for byte in 0..64 {
+        buffer.push(byte);
+    }
+    assert_eq!(buffer.capacity(), 64);
+    buffer.push(64);
+    assert_eq!(buffer.capacity(), 128);
+
+    let mut buffer = Buffer::new(allocator);
+    buffer.set_growth_policy(GrowthPolicy::Exact);
+    for byte in 0..65 {
+        buffer.push(byte);
+    }
+    assert_eq!(buffer.capacity(), 68);
+}
+
+#[test]
+fn shrink_tests() {
+    let allocator = Allocator::build()
+        .minimum_allocation_size(4)
+        .finish()
+        .unwrap();
+    let mut buffer = Buffer::with_capacity(1024, allocator.clone());
+    buffer.extend_from_slice(b"hello");
+    buffer.shrink_to(16);
+    assert_eq!(buffer.capacity(), 16);
+    buffer.shrink_to_fit();
+    assert_eq!(buffer.capacity(), 8);
+    assert_eq!(buffer.as_slice(), b"hello");
+    buffer.clear();
+    buffer.shrink_to_fit();
+    assert_eq!(buffer.capacity(), 0);
+
+    let mut buffer = Buffer::default();
+    buffer.extend_from_slice(b"hello");
+    buffer.reserve_capacity(1024);
+    buffer.shrink_to_fit();
+    assert_eq!(buffer.capacity(), 5);
+    assert_eq!(buffer.as_slice(), b"hello");
+}
+
+#[test]
+fn split_tests() {
+    let allocator = Allocator::build()
+        .minimum_allocation_size(4)
+        .finish()
+        .unwrap();
+    let mut buffer = Buffer::with_capacity(32, allocator);
+    buffer.extend_from_slice(b"header:body");
+    let address = buffer.as_ptr();
+
+    let mut header = buffer.split_to(7);
+    assert_eq!(header.as_slice(), b"header:");
+    assert_eq!(header.as_ptr(), address);
+    assert_eq!(buffer.as_slice(), b"body");
+    assert_eq!(buffer.capacity(), 25);
+
+    let tail = buffer.split_off(2);
+    assert_eq!(buffer.as_slice(), b"bo");
+    assert_eq!(tail.as_slice(), b"dy");
+    assert_eq!(tail.capacity(), 23);
+
+    // Each half can be modified independently.
+    header.as_slice_mut()[6] = b'=';
+    buffer.unsplit(tail);
+    assert_eq!(buffer.as_slice(), b"body");
+    assert_eq!(buffer.capacity(), 25);
+    header.unsplit(buffer);
+    assert_eq!(header.as_slice(), b"header=body");
+    assert_eq!(header.as_ptr(), address);
+
+    // Growing a half that shares its allocation must not overwrite the other
+    // half.
+    let mut body = header.split_off(7);
+    let address = header.as_ptr();
+    header.push(b'!');
+    assert_ne!(header.as_ptr(), address);
+    assert_eq!(header.as_slice(), b"header=!");
+    assert_eq!(body.as_slice(), b"body");
+
+    // Once the other half is dropped, the remaining half owns the allocation
+    // and can grow it in place.
+    drop(header);
+    let address = body.as_ptr();
+    body.extend_from_slice(&[b'.'; 30]);
+    assert_eq!(body.as_ptr(), address);
+    assert_eq!(&body[..4], b"body");
+    let frozen = body.freeze();
+    assert_eq!(frozen.len(), 34);
+}
+
+#[test]
+fn editing_tests() {
+    let allocator = Allocator::build()
+        .minimum_allocation_size(4)
+        .finish()
+        .unwrap();
+    let mut buffer = Buffer::new(allocator);
+    buffer.extend_from_slice(b"hello");
+
+    buffer.insert(0, b'>');
+    buffer.insert(6, b'!');
+    assert_eq!(buffer.as_slice(), b">hello!");
+    buffer.insert_from_slice(6, b", world");
+    assert_eq!(buffer.as_slice(), b">hello, world!");
+    assert_eq!(buffer.remove(0), b'>');
+    assert_eq!(buffer.swap_remove(1), b'e');
+    assert_eq!(buffer.as_slice(), b"h!llo, world");
+    assert_eq!(buffer.pop(), Some(b'd'));
+    buffer.truncate(5);
+    assert_eq!(buffer.as_slice(), b"h!llo");
+
+    buffer.retain(|byte| *byte != b'!');
+    assert_eq!(buffer.as_slice(), b"hllo");
+    buffer.dedup();
+    assert_eq!(buffer.as_slice(), b"hlo");
+
+    buffer.resize(5, b'o');
+    assert_eq!(buffer.as_slice(), b"hlooo");
+    buffer.resize(1, 0);
+    assert_eq!(buffer.as_slice(), b"h");
+    assert_eq!(buffer.pop(), Some(b'h'));
+    assert_eq!(buffer.pop(), None);
+}
+
+#[test]
+#[should_panic = "insertion index (is 2) should be <= len (is 1)"]
+fn insert_out_of_bounds() {
+    let mut buffer = Buffer::default();
+    buffer.push(0);
+    buffer.insert(2, 0);
+}
+
+#[test]
+fn trait_tests() {
+    use std::collections::HashSet;
+
+    let allocator = Allocator::build()
+        .minimum_allocation_size(4)
+        .finish()
+        .unwrap();
+    let mut buffer = Buffer::new(allocator);
+    buffer.extend_from_slice(b"hello\n");
+    assert_eq!(format!("{buffer:?}"), r#"b"hello\n""#);
+
+    let clone = buffer.clone();
+    assert_eq!(clone, buffer);
+    assert_ne!(clone.as_ptr(), buffer.as_ptr());
+    assert_eq!(buffer, b"hello\n");
+    assert_eq!(buffer, *b"hello\n");
+    assert_eq!(buffer, b"hello\n".to_vec());
+    assert_eq!(b"hello\n".as_slice(), buffer);
+
+    let a = Buffer::from(&b"a"[..]);
+    let b = Buffer::from(&b"b"[..]);
+    assert!(a < b);
+    // Buffer's hash and equality only depend on its contents.
+    #[allow(clippy::mutable_key_type)]
+    let mut set = HashSet::new();
+    set.insert(buffer);
+    assert!(set.contains(b"hello\n".as_slice()));
+
+    let collected = (b'a'..=b'e').collect::<Buffer>();
+    assert_eq!(collected, b"abcde");
+    let mut extended = Buffer::default();
+    Extend::extend(&mut extended, b"abc");
+    Extend::extend(&mut extended, [b'd', b'e']);
+    assert_eq!(extended, collected);
+
+    let mut vec = Vec::with_capacity(16);
+    vec.extend_from_slice(b"vec");
+    let address = vec.as_ptr();
+    let buffer = Buffer::from(vec);
+    assert_eq!(buffer, b"vec");
+    assert_eq!(buffer.capacity(), 16);
+    let vec = Vec::from(buffer);
+    assert_eq!(vec, b"vec");
+    assert_eq!(vec.as_ptr(), address);
+    assert_eq!(Vec::from(clone), b"hello\n");
+}
+
+#[test]
+fn spare_capacity_tests() {
+    let allocator = Allocator::build()
+        .minimum_allocation_size(4)
+        .finish()
+        .unwrap();
+    // Dirty the memory the next allocation will reuse.
+    let mut dirty = Buffer::with_capacity(8, allocator.clone());
+    dirty.extend_from_slice(b"garbage!");
+    drop(dirty);
+
+    let mut buffer = Buffer::with_len(4, allocator.clone());
+    assert_eq!(buffer, [0; 4]);
+    buffer.as_slice_mut().copy_from_slice(b"data");
+    buffer.truncate(0);
+    buffer.set_len(2);
+    assert_eq!(buffer, [0; 2]);
+
+    let spare = buffer.spare_capacity_mut();
+    assert_eq!(spare.len(), 2);
+    spare[0].write(b'!');
+    // SAFETY: The first byte of spare capacity was initialized.
+    unsafe { buffer.advance_len(1) };
+    assert_eq!(buffer, [0, 0, b'!']);
+}
+
+#[test]
+fn read_from_tests() {
+    let allocator = Allocator::build()
+        .minimum_allocation_size(4)
+        .finish()
+        .unwrap();
+    let mut buffer = Buffer::new(allocator);
+    let mut input = &b"hello, world!"[..];
+    assert_eq!(buffer.read_from(&mut input, 5).unwrap(), 5);
+    assert_eq!(buffer, b"hello");
+    buffer.read_exact_from(&mut input, 2).unwrap();
+    assert_eq!(buffer, b"hello, ");
+    assert_eq!(
+        buffer
+            .read_exact_from(&mut &b"wo"[..], 3)
+            .unwrap_err()
+            .kind(),
+        io::ErrorKind::UnexpectedEof
+    );
+    assert_eq!(buffer, b"hello, ");
+    assert_eq!(buffer.read_to_end_from(&mut input).unwrap(), 6);
+    assert_eq!(buffer, b"hello, world!");
+
+    let large = vec![42; 1000];
+    assert_eq!(buffer.read_to_end_from(&mut &large[..]).unwrap(), 1000);
+    assert_eq!(buffer.len(), 1013);
+    assert!(buffer[13..].iter().all(|byte| *byte == 42));
+}
+
+#[test]
+fn fallible_allocation() {
+    use std::{
+        panic::{self, AssertUnwindSafe},
+        sync::atomic::{AtomicBool, Ordering},
+    };
+
+    static HOOK_CALLED: AtomicBool = AtomicBool::new(false);
+
+    let mut buffer = Buffer::default();
+    buffer.try_extend_from_slice(b"hello").unwrap();
+    buffer.try_push(b'!').unwrap();
+    assert_eq!(buffer, b"hello!");
+    assert!(matches!(
+        buffer.try_reserve(usize::MAX),
+        Err(AllocError::CapacityOverflow)
+    ));
+    assert!(matches!(
+        buffer.try_reserve_capacity(isize::MAX as usize),
+        Err(AllocError::OutOfMemory { .. })
+    ));
+    assert_eq!(buffer, b"hello!");
+
+    let allocator = Allocator::build()
+        .out_of_memory_hook(|err| {
+            assert!(matches!(err, AllocError::CapacityOverflow));
+            HOOK_CALLED.store(true, Ordering::Relaxed);
+        })
+        .finish()
+        .unwrap();
+    assert!(allocator.try_allocate(usize::MAX).is_err());
+    assert!(!HOOK_CALLED.load(Ordering::Relaxed));
+    let mut buffer = Buffer::new(allocator);
+    buffer.push(1);
+    let result = panic::catch_unwind(AssertUnwindSafe(|| buffer.reserve_capacity(usize::MAX)));
+    assert!(result.is_err());
+    assert!(HOOK_CALLED.load(Ordering::Relaxed));
+}
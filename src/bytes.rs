@@ -0,0 +1,163 @@
+use std::{
+    borrow::Borrow,
+    fmt::{self, Debug},
+    hash::{Hash, Hasher},
+    ops::{Deref, Range, RangeBounds},
+    slice,
+    sync::Arc,
+};
+
+use crate::{buffer::resolve_range, Allocation, Buffer};
+
+/// An immutable, reference counted view of bytes created by
+/// [`Buffer::freeze()`].
+///
+/// Cloning and slicing share the underlying [`Allocation`], which is freed
+/// once the last view of it is dropped.
+#[derive(Clone, Default)]
+pub struct Bytes {
+    allocation: Option<Arc<Allocation>>,
+    offset: usize,
+    length: usize,
+}
+
+impl Bytes {
+    pub(crate) const fn new(
+        allocation: Option<Arc<Allocation>>,
+        offset: usize,
+        length: usize,
+    ) -> Self {
+        Self {
+            allocation,
+            offset,
+            length,
+        }
+    }
+
+    /// Returns the number of bytes in this view.
+    #[must_use]
+    pub const fn len(&self) -> usize {
+        self.length
+    }
+
+    /// Returns true if this view contains no bytes.
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    /// Returns the contents of this view.
+    #[must_use]
+    pub fn as_slice(&self) -> &[u8] {
+        match &self.allocation {
+            // SAFETY: `offset..offset + length` is within the allocation and
+            // is never mutated while a view of it exists. Other regions of
+            // the allocation may be owned and mutated by other buffers, so no
+            // reference to the whole allocation is created.
+            Some(allocation) => unsafe {
+                slice::from_raw_parts(allocation.address().add(self.offset), self.length)
+            },
+            None => &[],
+        }
+    }
+
+    /// Returns a view of `range` within this view, sharing the same
+    /// allocation.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `range` is out of bounds or if its start is greater than its
+    /// end.
+    #[must_use]
+    pub fn slice(&self, range: impl RangeBounds<usize>) -> Self {
+        let Range { start, end } = resolve_range(range, self.length);
+
+        if start == end {
+            return Self::default();
+        }
+
+        Self {
+            allocation: self.allocation.clone(),
+            offset: self.offset + start,
+            length: end - start,
+        }
+    }
+}
+
+impl From<Buffer> for Bytes {
+    fn from(buffer: Buffer) -> Self {
+        buffer.freeze()
+    }
+}
+
+impl Debug for Bytes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "b\"{}\"", self.escape_ascii())
+    }
+}
+
+impl Deref for Bytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        self.as_slice()
+    }
+}
+
+impl AsRef<[u8]> for Bytes {
+    fn as_ref(&self) -> &[u8] {
+        self.as_slice()
+    }
+}
+
+impl Borrow<[u8]> for Bytes {
+    fn borrow(&self) -> &[u8] {
+        self.as_slice()
+    }
+}
+
+impl PartialEq for Bytes {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_slice() == other.as_slice()
+    }
+}
+
+impl Eq for Bytes {}
+
+impl PartialEq<[u8]> for Bytes {
+    fn eq(&self, other: &[u8]) -> bool {
+        self.as_slice() == other
+    }
+}
+
+impl Hash for Bytes {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_slice().hash(state);
+    }
+}
+
+#[test]
+fn basic_tests() {
+    use crate::Allocator;
+
+    let allocator = Allocator::build()
+        .minimum_allocation_size(4)
+        .finish()
+        .unwrap();
+    let mut buffer = Buffer::with_capacity(1024, allocator);
+    buffer.extend_from_slice(b"hello, world!");
+    let bytes = buffer.freeze();
+    assert_eq!(bytes.as_slice(), b"hello, world!");
+
+    let hello = bytes.slice(..5);
+    let world = bytes.slice(7..12);
+    drop(bytes);
+    assert_eq!(hello.as_slice(), b"hello");
+    assert_eq!(world.as_slice(), b"world");
+    assert_eq!(world.slice(1..=2).as_slice(), b"or");
+    assert!(world.slice(5..).is_empty());
+
+    let cloned = hello.clone();
+    assert_eq!(cloned, hello);
+    assert_eq!(cloned.as_ptr(), hello.as_ptr());
+}
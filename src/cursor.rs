@@ -0,0 +1,217 @@
+use std::io::{self, BufRead, Read, Seek, SeekFrom};
+
+use crate::Buffer;
+
+/// A cursor that reads the contents of a [`Buffer`], implementing [`Read`],
+/// [`BufRead`] and [`Seek`].
+///
+/// `B` can be a borrowed buffer, created using [`Buffer::cursor()`], or an
+/// owned buffer, created using [`Buffer::into_cursor()`].
+#[derive(Debug, Clone)]
+pub struct BufferCursor<B> {
+    buffer: B,
+    position: u64,
+}
+
+impl<B> BufferCursor<B> {
+    /// Returns a cursor positioned at the start of `buffer`.
+    pub const fn new(buffer: B) -> Self {
+        Self {
+            buffer,
+            position: 0,
+        }
+    }
+
+    /// Returns the current position of this cursor.
+    #[must_use]
+    pub const fn position(&self) -> u64 {
+        self.position
+    }
+
+    /// Sets the position of this cursor. The position may be past the end of
+    /// the buffer.
+    pub fn set_position(&mut self, position: u64) {
+        self.position = position;
+    }
+
+    /// Returns a reference to the underlying buffer.
+    pub const fn get_ref(&self) -> &B {
+        &self.buffer
+    }
+
+    /// Returns the underlying buffer.
+    pub fn into_inner(self) -> B {
+        self.buffer
+    }
+}
+
+impl<B> BufferCursor<B>
+where
+    B: AsRef<[u8]>,
+{
+    /// Returns the bytes after the current position.
+    #[must_use]
+    pub fn remaining_slice(&self) -> &[u8] {
+        let bytes = self.buffer.as_ref();
+        let start = usize::try_from(self.position)
+            .map_or(bytes.len(), |position| position.min(bytes.len()));
+        &bytes[start..]
+    }
+}
+
+impl<B> Read for BufferCursor<B>
+where
+    B: AsRef<[u8]>,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = self.remaining_slice();
+        let amount = remaining.len().min(buf.len());
+        buf[..amount].copy_from_slice(&remaining[..amount]);
+        self.consume(amount);
+        Ok(amount)
+    }
+}
+
+impl<B> BufRead for BufferCursor<B>
+where
+    B: AsRef<[u8]>,
+{
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        Ok(self.remaining_slice())
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.position += amount as u64;
+    }
+}
+
+impl<B> Seek for BufferCursor<B>
+where
+    B: AsRef<[u8]>,
+{
+    fn seek(&mut self, style: SeekFrom) -> io::Result<u64> {
+        let (base, offset) = match style {
+            SeekFrom::Start(position) => {
+                self.position = position;
+                return Ok(position);
+            }
+            SeekFrom::End(offset) => (self.buffer.as_ref().len() as u64, offset),
+            SeekFrom::Current(offset) => (self.position, offset),
+        };
+        match base.checked_add_signed(offset) {
+            Some(position) => {
+                self.position = position;
+                Ok(position)
+            }
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )),
+        }
+    }
+
+    fn stream_position(&mut self) -> io::Result<u64> {
+        Ok(self.position)
+    }
+}
+
+/// A reader that consumes the contents of a [`Buffer`] as they are read.
+///
+/// Created using [`Buffer::into_reader()`]. As bytes are read, the stripes
+/// that no longer contain unread bytes are returned to the slab.
+#[derive(Debug, Default)]
+pub struct BufferReader {
+    buffer: Buffer,
+}
+
+impl BufferReader {
+    /// Returns a reader that consumes `buffer`.
+    #[must_use]
+    pub const fn new(buffer: Buffer) -> Self {
+        Self { buffer }
+    }
+
+    /// Returns the unread bytes.
+    #[must_use]
+    pub fn as_slice(&self) -> &[u8] {
+        self.buffer.as_slice()
+    }
+
+    /// Returns a buffer containing the unread bytes.
+    #[must_use]
+    pub fn into_inner(self) -> Buffer {
+        self.buffer
+    }
+}
+
+impl Read for BufferReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let amount = self.buffer.len().min(buf.len());
+        buf[..amount].copy_from_slice(&self.buffer[..amount]);
+        self.buffer.consume(amount);
+        Ok(amount)
+    }
+}
+
+impl BufRead for BufferReader {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        Ok(self.buffer.as_slice())
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.buffer.consume(amount);
+    }
+}
+
+#[test]
+fn cursor_tests() {
+    let buffer = Buffer::from(&b"hello\nworld"[..]);
+    let mut cursor = buffer.cursor();
+    let mut line = String::new();
+    cursor.read_line(&mut line).unwrap();
+    assert_eq!(line, "hello\n");
+    assert_eq!(cursor.position(), 6);
+
+    assert_eq!(cursor.seek(SeekFrom::End(-2)).unwrap(), 9);
+    let mut rest = Vec::new();
+    cursor.read_to_end(&mut rest).unwrap();
+    assert_eq!(rest, b"ld");
+    assert!(cursor.seek(SeekFrom::Current(-12)).is_err());
+
+    let mut cursor = buffer.into_cursor();
+    cursor.seek(SeekFrom::Start(6)).unwrap();
+    let mut word = [0; 5];
+    cursor.read_exact(&mut word).unwrap();
+    assert_eq!(&word, b"world");
+    assert_eq!(cursor.into_inner(), b"hello\nworld");
+}
+
+#[test]
+fn reader_tests() {
+    use crate::Allocator;
+
+    let allocator = Allocator::build()
+        .minimum_allocation_size(4)
+        .finish()
+        .unwrap();
+    let mut buffer = Buffer::with_capacity(16, allocator.clone());
+    buffer.extend_from_slice(b"hello, world");
+    let address = buffer.as_ptr();
+
+    let mut reader = buffer.into_reader();
+    let mut hello = [0; 7];
+    reader.read_exact(&mut hello).unwrap();
+    assert_eq!(&hello, b"hello, ");
+    assert_eq!(reader.as_slice(), b"world");
+
+    // The first two stripes were fully consumed and can be allocated again,
+    // even when a guard stripe must follow the allocation.
+    let mut w = [0; 1];
+    reader.read_exact(&mut w).unwrap();
+    let reused = allocator.allocate(4);
+    assert_eq!(reused.address().cast_const(), address);
+
+    let buffer = reader.into_inner();
+    assert_eq!(buffer, b"orld");
+    assert_eq!(buffer.capacity(), 8);
+}
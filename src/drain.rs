@@ -0,0 +1,172 @@
+use std::{iter::FusedIterator, ops::Range};
+
+use crate::Buffer;
+
+/// An iterator over the bytes removed from a [`Buffer`] by
+/// [`Buffer::drain()`].
+#[derive(Debug)]
+pub struct Drain<'a> {
+    buffer: &'a mut Buffer,
+    remaining: Range<usize>,
+    tail_start: usize,
+    tail_length: usize,
+}
+
+impl<'a> Drain<'a> {
+    pub(crate) fn new(buffer: &'a mut Buffer, range: Range<usize>) -> Self {
+        let length = buffer.len();
+        // Until the drain is finished, the buffer only contains the bytes
+        // before the drained range. If this iterator is leaked, the buffer
+        // remains truncated rather than exposing drained bytes.
+        buffer.set_len_within_capacity(range.start);
+        Self {
+            buffer,
+            tail_start: range.end,
+            tail_length: length - range.end,
+            remaining: range,
+        }
+    }
+
+    /// Returns the bytes that have not been yielded by this iterator.
+    #[must_use]
+    pub fn as_slice(&self) -> &[u8] {
+        &self.buffer.region()[self.remaining.clone()]
+    }
+
+    /// Moves the bytes after the drained range into place.
+    fn finish(&mut self) {
+        if self.tail_length > 0 {
+            let start = self.buffer.len();
+            self.buffer
+                .region_mut()
+                .copy_within(self.tail_start..self.tail_start + self.tail_length, start);
+            self.buffer
+                .set_len_within_capacity(start + self.tail_length);
+            self.tail_length = 0;
+        }
+        self.remaining = self.tail_start..self.tail_start;
+    }
+}
+
+impl Iterator for Drain<'_> {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let index = self.remaining.next()?;
+        Some(self.buffer.region()[index])
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.remaining.size_hint()
+    }
+}
+
+impl DoubleEndedIterator for Drain<'_> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let index = self.remaining.next_back()?;
+        Some(self.buffer.region()[index])
+    }
+}
+
+impl ExactSizeIterator for Drain<'_> {}
+
+impl FusedIterator for Drain<'_> {}
+
+impl Drop for Drain<'_> {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+/// An iterator over the bytes removed from a [`Buffer`] by
+/// [`Buffer::splice()`].
+///
+/// The replacement bytes are inserted when this iterator is dropped.
+#[derive(Debug)]
+pub struct Splice<'a, I: Iterator<Item = u8>> {
+    drain: Drain<'a>,
+    replace_with: I,
+}
+
+impl<'a, I: Iterator<Item = u8>> Splice<'a, I> {
+    pub(crate) const fn new(drain: Drain<'a>, replace_with: I) -> Self {
+        Self {
+            drain,
+            replace_with,
+        }
+    }
+}
+
+impl<I: Iterator<Item = u8>> Iterator for Splice<'_, I> {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.drain.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.drain.size_hint()
+    }
+}
+
+impl<I: Iterator<Item = u8>> DoubleEndedIterator for Splice<'_, I> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.drain.next_back()
+    }
+}
+
+impl<I: Iterator<Item = u8>> ExactSizeIterator for Splice<'_, I> {}
+
+impl<I: Iterator<Item = u8>> Drop for Splice<'_, I> {
+    fn drop(&mut self) {
+        let insert_at = self.drain.buffer.len();
+        self.drain.finish();
+
+        // Append the replacement bytes after the tail, then rotate them into
+        // position in front of it.
+        let tail_end = self.drain.buffer.len();
+        self.drain.buffer.extend(self.replace_with.by_ref());
+        let inserted = self.drain.buffer.len() - tail_end;
+        self.drain.buffer[insert_at..].rotate_right(inserted);
+    }
+}
+
+#[test]
+fn drain_tests() {
+    let mut buffer = Buffer::default();
+    buffer.extend_from_slice(b"hello, world!");
+
+    let mut drain = buffer.drain(5..12);
+    assert_eq!(drain.next(), Some(b','));
+    assert_eq!(drain.next_back(), Some(b'd'));
+    assert_eq!(drain.as_slice(), b" worl");
+    drop(drain);
+    assert_eq!(buffer.as_slice(), b"hello!");
+
+    let drained = buffer.drain(..=1).collect::<Vec<u8>>();
+    assert_eq!(drained, b"he");
+    assert_eq!(buffer.as_slice(), b"llo!");
+
+    // Leaking the iterator truncates the buffer.
+    std::mem::forget(buffer.drain(2..));
+    assert_eq!(buffer.as_slice(), b"ll");
+}
+
+#[test]
+fn splice_tests() {
+    let mut buffer = Buffer::default();
+    buffer.extend_from_slice(b"hello, world!");
+
+    let removed = buffer
+        .splice(7..12, b"rebytes".iter().copied())
+        .collect::<Vec<u8>>();
+    assert_eq!(removed, b"world");
+    assert_eq!(buffer.as_slice(), b"hello, rebytes!");
+
+    drop(buffer.splice(..5, []));
+    assert_eq!(buffer.as_slice(), b", rebytes!");
+    drop(buffer.splice(..0, *b"hi"));
+    assert_eq!(buffer.as_slice(), b"hi, rebytes!");
+    drop(buffer.splice(10.., *b"..."));
+    assert_eq!(buffer.as_slice(), b"hi, rebyte...");
+}
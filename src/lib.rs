@@ -1,3 +1,4 @@
+#![doc = include_str!("../README.md")]
 #![warn(
     clippy::pedantic,
     clippy::cargo,
@@ -10,10 +11,27 @@
 mod allocation;
 mod allocator;
 mod buffer;
+mod bytes;
+mod cursor;
+mod drain;
+mod notify;
+mod placement;
 mod slab;
+mod slablist;
 mod slabring;
+mod statistics;
+mod threadcache;
+mod tracking;
 pub use self::{
-    allocation::Allocation,
-    allocator::{Allocator, Config},
-    buffer::Buffer,
+    allocation::{AllocError, Allocation},
+    allocator::{Allocator, Config, ConfigError, OutOfMemoryHook},
+    buffer::{Buffer, GrowthPolicy},
+    bytes::Bytes,
+    cursor::{BufferCursor, BufferReader},
+    drain::{Drain, Splice},
+    placement::{BestFit, FirstFit, NextFit, PlacementStrategy},
+    slab::{FreeSpan, ScrubPolicy, SlabLayout},
+    slabring::{LimitPolicy, LimitScope},
+    statistics::Statistics,
+    tracking::{AllocationTracking, LeakReport, OutstandingAllocation},
 };
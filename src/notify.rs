@@ -0,0 +1,137 @@
+use std::{
+    mem,
+    sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+    task::Waker,
+    time::Instant,
+};
+
+use parking_lot::{Condvar, Mutex};
+
+/// Wakes threads and tasks waiting for slab memory to be freed.
+///
+/// Waiters observe the current generation before attempting to allocate, and
+/// only wait if no memory has been freed since. Freeing memory only acquires
+/// the lock when there are waiters, and does nothing without a memory limit.
+#[derive(Debug, Default)]
+pub struct MemoryNotifier {
+    /// Whether a memory limit is set. Without one, nothing waits for memory.
+    limited: AtomicBool,
+    generation: AtomicU64,
+    waiters: AtomicUsize,
+    /// The registered wakers, keyed by the registration they belong to.
+    wakers: Mutex<Vec<(u64, Waker)>>,
+    next_registration: AtomicU64,
+    condvar: Condvar,
+}
+
+impl MemoryNotifier {
+    /// Returns a new notifier for an allocator with or without a memory
+    /// limit.
+    pub fn new(limited: bool) -> Self {
+        Self {
+            limited: AtomicBool::new(limited),
+            ..Self::default()
+        }
+    }
+
+    /// Sets whether a memory limit is set. Call [`Self::notify()`] afterwards
+    /// to wake waiters if the limit was raised or removed.
+    pub fn set_limited(&self, limited: bool) {
+        self.limited.store(limited, Ordering::SeqCst);
+    }
+
+    /// Returns the current generation, which changes each time memory is
+    /// freed.
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::SeqCst)
+    }
+
+    /// Blocks the current thread until memory has been freed after
+    /// `generation` was observed. Returns false if `deadline` passed first.
+    pub fn wait(&self, generation: u64, deadline: Option<Instant>) -> bool {
+        self.waiters.fetch_add(1, Ordering::SeqCst);
+        let mut wakers = self.wakers.lock();
+        let mut freed = true;
+        while self.generation() == generation {
+            match deadline {
+                Some(deadline) => {
+                    if self.condvar.wait_until(&mut wakers, deadline).timed_out() {
+                        freed = self.generation() != generation;
+                        break;
+                    }
+                }
+                None => self.condvar.wait(&mut wakers),
+            }
+        }
+        drop(wakers);
+        self.waiters.fetch_sub(1, Ordering::SeqCst);
+        freed
+    }
+
+    /// Registers `waker` to be woken when memory is freed. Returns false
+    /// without registering if memory has been freed since `generation` was
+    /// observed.
+    ///
+    /// `registration` identifies the waiting task's registration. If it is
+    /// still registered, its waker is replaced. Otherwise a new registration
+    /// is stored in `registration`, which should be passed to
+    /// [`Self::deregister()`] if the task stops waiting before it is woken.
+    pub fn register(&self, generation: u64, waker: &Waker, registration: &mut Option<u64>) -> bool {
+        self.waiters.fetch_add(1, Ordering::SeqCst);
+        let mut wakers = self.wakers.lock();
+        if self.generation() != generation {
+            drop(wakers);
+            self.waiters.fetch_sub(1, Ordering::SeqCst);
+            return false;
+        }
+        let registered = registration
+            .and_then(|key| wakers.iter_mut().find(|(registered, _)| *registered == key));
+        if let Some((_, registered)) = registered {
+            registered.clone_from(waker);
+            self.waiters.fetch_sub(1, Ordering::SeqCst);
+        } else {
+            let key = self.next_registration.fetch_add(1, Ordering::Relaxed);
+            wakers.push((key, waker.clone()));
+            *registration = Some(key);
+        }
+        true
+    }
+
+    /// Removes `registration`'s waker if it has not been woken yet.
+    pub fn deregister(&self, registration: u64) {
+        if self.waiters.load(Ordering::SeqCst) == 0 {
+            return;
+        }
+        let mut wakers = self.wakers.lock();
+        if let Some(index) = wakers.iter().position(|(key, _)| *key == registration) {
+            wakers.swap_remove(index);
+            drop(wakers);
+            self.waiters.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+
+    /// Notifies waiters that memory has been freed, if a memory limit is set.
+    pub fn notify_freed(&self) {
+        if self.limited.load(Ordering::SeqCst) {
+            self.notify();
+        }
+    }
+
+    /// Notifies waiters that memory may be available.
+    pub fn notify(&self) {
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        if self.waiters.load(Ordering::SeqCst) > 0 {
+            // Acquiring the lock ensures that a waiter that observed the
+            // previous generation is either waiting on the condvar or has
+            // registered its waker.
+            let mut registered = self.wakers.lock();
+            self.condvar.notify_all();
+            let wakers = mem::take(&mut *registered);
+            drop(registered);
+            self.waiters.fetch_sub(wakers.len(), Ordering::SeqCst);
+            for (_, waker) in wakers {
+                waker.wake();
+            }
+        }
+    }
+}
@@ -0,0 +1,70 @@
+use std::fmt::Debug;
+
+use crate::slab::FreeSpan;
+
+/// Chooses the free span of a slab that each allocation is placed in.
+///
+/// A strategy is shared by all slabs of an [`Allocator`](crate::Allocator)
+/// and is consulted while the slab is locked, so it should be quick. The
+/// slab remains responsible for splitting and merging its free spans.
+pub trait PlacementStrategy: Debug + Send + Sync + 'static {
+    /// Returns the index within `free_spans` of the span to allocate
+    /// `stripes` stripes from, or `None` to treat the slab as full.
+    ///
+    /// `free_spans` is ordered by offset and never contains adjacent spans.
+    /// `stripes` includes the allocation's guard stripe when the
+    /// `debug-checks` feature is enabled. `cursor` is the offset at which the
+    /// previous allocation from the slab ended. Returning a span with fewer
+    /// than `stripes` stripes is treated as `None`.
+    fn select(&self, free_spans: &[FreeSpan], stripes: usize, cursor: usize) -> Option<usize>;
+}
+
+/// Places each allocation in the smallest free span it fits in.
+///
+/// This is the default strategy. It leaves the largest spans intact for
+/// larger allocations, but searches every span unless an exact fit is found.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BestFit;
+
+impl PlacementStrategy for BestFit {
+    fn select(&self, free_spans: &[FreeSpan], stripes: usize, _cursor: usize) -> Option<usize> {
+        let mut best: Option<(usize, usize)> = None;
+        for (index, span) in free_spans.iter().enumerate() {
+            if let Some(extra_stripes) = span.stripes().checked_sub(stripes) {
+                if best.is_none_or(|(_, best_extra)| extra_stripes < best_extra) {
+                    best = Some((index, extra_stripes));
+                    if extra_stripes == 0 {
+                        break;
+                    }
+                }
+            }
+        }
+        best.map(|(index, _)| index)
+    }
+}
+
+/// Places each allocation in the lowest-addressed free span it fits in,
+/// keeping allocations packed towards the start of each slab.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FirstFit;
+
+impl PlacementStrategy for FirstFit {
+    fn select(&self, free_spans: &[FreeSpan], stripes: usize, _cursor: usize) -> Option<usize> {
+        free_spans.iter().position(|span| span.stripes() >= stripes)
+    }
+}
+
+/// Places each allocation in the first free span it fits in, starting where
+/// the previous allocation from the slab ended and wrapping around to the
+/// start of the slab.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NextFit;
+
+impl PlacementStrategy for NextFit {
+    fn select(&self, free_spans: &[FreeSpan], stripes: usize, cursor: usize) -> Option<usize> {
+        let start = free_spans.partition_point(|span| span.offset() < cursor);
+        (start..free_spans.len())
+            .chain(0..start)
+            .find(|index| free_spans[*index].stripes() >= stripes)
+    }
+}
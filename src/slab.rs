@@ -1,11 +1,43 @@
 use std::{
     alloc::{self, Layout},
-    sync::Arc,
+    slice,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
 };
 
 use parking_lot::Mutex;
 
-use crate::allocation::Allocation;
+use crate::{
+    allocation::Allocation,
+    notify::MemoryNotifier,
+    placement::{BestFit, PlacementStrategy},
+    threadcache::ThreadCache,
+};
+
+/// When enabled, freed memory is poisoned and verified before it is reused, and
+/// each allocation is followed by a guard stripe that is verified when the
+/// allocation is freed.
+const DEBUG_CHECKS: bool = cfg!(feature = "debug-checks");
+/// The number of guard stripes following each allocation.
+pub const GUARD_STRIPES: usize = if DEBUG_CHECKS { 1 } else { 0 };
+/// The byte freed memory is filled with when `debug-checks` is enabled.
+const POISON: u8 = 0xDF;
+/// The byte guard stripes are filled with when `debug-checks` is enabled.
+const CANARY: u8 = 0xCA;
+
+static NEXT_SLAB_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// Rounds `stripes` up to its size class. Classes are spaced four per doubling
+/// of the number of stripes.
+pub fn size_class(stripes: usize) -> usize {
+    if stripes <= 4 {
+        stripes
+    } else {
+        stripes.next_multiple_of(1 << (stripes.ilog2() - 2))
+    }
+}
 
 /// A reference counted, fixed-size allocation of memory.
 #[derive(Debug, Clone)]
@@ -15,10 +47,73 @@ pub struct Slab {
 
 #[derive(Debug)]
 struct Data {
+    id: usize,
     layout: Layout,
     minimum_allocation_size: usize,
+    scrub: ScrubPolicy,
+    notifier: Arc<MemoryNotifier>,
+    thread_cache: Option<ThreadCache>,
     bytes: *mut u8,
-    free_spans: Mutex<Vec<Span>>,
+    free_spans: Mutex<Vec<FreeSpan>>,
+    /// One bit per stripe, set when the stripe is freed. Freeing memory never
+    /// waits for `free_spans`: the pending stripes are merged into
+    /// `free_spans` by whichever thread next holds its lock.
+    pending_frees: Box<[AtomicU64]>,
+    has_pending_frees: AtomicBool,
+    /// The number of free stripes, including pending frees. Allows a slab in
+    /// use by another thread to be recognized as full without its lock.
+    free_stripes: AtomicUsize,
+    occupancy: Occupancy,
+}
+
+/// How a slab tracks which of its memory is allocated.
+#[derive(Debug)]
+enum Occupancy {
+    /// Free memory is tracked in `free_spans`, and `strategy` chooses the span
+    /// each allocation is placed in.
+    Spans {
+        strategy: Arc<dyn PlacementStrategy>,
+        /// The offset at which the previous allocation ended. Only changed
+        /// while `free_spans` is locked.
+        cursor: AtomicUsize,
+    },
+    /// The slab only serves allocations of a single size class, and
+    /// `free_spans` is unused.
+    SizeClass(SizeClass),
+}
+
+/// How a new slab places allocations within its memory.
+#[derive(Debug, Clone)]
+pub enum Placement {
+    /// Allocations of any size are placed in the free spans chosen by a
+    /// strategy.
+    Spans(Arc<dyn PlacementStrategy>),
+    /// Only allocations of this many stripes are served.
+    SizeClass(usize),
+}
+
+impl Default for Placement {
+    fn default() -> Self {
+        Self::Spans(Arc::new(BestFit))
+    }
+}
+
+/// The occupancy of a slab that serves a single size class.
+#[derive(Debug)]
+struct SizeClass {
+    /// The stripes of each allocation, excluding its guard stripe.
+    stripes: usize,
+    /// One bit per slot, set while the slot is allocated.
+    occupied: Box<[AtomicU64]>,
+    /// The first word of `occupied` that may have a clear bit. Searches
+    /// start here and wrap around, so a stale hint only costs time.
+    first_free: AtomicUsize,
+}
+
+impl SizeClass {
+    fn slot_stripes(&self) -> usize {
+        self.stripes + GUARD_STRIPES
+    }
 }
 
 // SAFETY: u8 is Send, and data is always initialized.
@@ -28,86 +123,563 @@ unsafe impl Send for Data {}
 unsafe impl Sync for Data {}
 
 impl Slab {
-    pub fn new(length: usize, layout: Layout, minimum_allocation_size: usize) -> Self {
+    /// Returns a new slab, or `None` if its memory cannot be allocated.
+    /// `notifier` is notified whenever memory is returned to this slab. When
+    /// `thread_cache` is provided, freed allocations are kept in it before
+    /// being returned to this slab.
+    pub fn new(
+        length: usize,
+        layout: Layout,
+        minimum_allocation_size: usize,
+        scrub: ScrubPolicy,
+        notifier: Arc<MemoryNotifier>,
+        thread_cache: Option<ThreadCache>,
+        placement: Placement,
+    ) -> Option<Self> {
         let total_stripes = length / minimum_allocation_size;
-        // SAFETY: This can panic in out of memory situations, but no undefined
-        // behavior should be possible from this call. This pointer is dealloced
-        // in Drop.
+        let (free_stripes, occupancy) = match placement {
+            Placement::SizeClass(stripes) => {
+                let slots = total_stripes / (stripes + GUARD_STRIPES);
+                // The bits past the last slot are permanently occupied.
+                let occupied = (0..slots.div_ceil(64))
+                    .map(|word| {
+                        let slots_in_word = (slots - word * 64).min(64);
+                        AtomicU64::new(if slots_in_word == 64 {
+                            0
+                        } else {
+                            u64::MAX << slots_in_word
+                        })
+                    })
+                    .collect();
+                (
+                    slots * (stripes + GUARD_STRIPES),
+                    Occupancy::SizeClass(SizeClass {
+                        stripes,
+                        occupied,
+                        first_free: AtomicUsize::new(0),
+                    }),
+                )
+            }
+            Placement::Spans(strategy) => (
+                total_stripes,
+                Occupancy::Spans {
+                    strategy,
+                    cursor: AtomicUsize::new(0),
+                },
+            ),
+        };
+        // SAFETY: The returned pointer is checked for null before use, and is
+        // dealloced in Drop.
         let bytes = unsafe { alloc::alloc_zeroed(layout) };
-        Self {
+        if bytes.is_null() {
+            return None;
+        }
+        if DEBUG_CHECKS {
+            // SAFETY: bytes was just allocated using layout.
+            unsafe { bytes.write_bytes(POISON, layout.size()) };
+        }
+        Some(Self {
             data: Arc::new(Data {
+                id: NEXT_SLAB_ID.fetch_add(1, Ordering::Relaxed),
                 layout,
                 minimum_allocation_size,
+                scrub,
+                notifier,
+                thread_cache,
                 bytes,
-                free_spans: Mutex::new(vec![Span {
-                    offset: 0,
-                    stripes: total_stripes,
-                }]),
+                free_spans: Mutex::new(match occupancy {
+                    Occupancy::Spans { .. } => vec![FreeSpan {
+                        offset: 0,
+                        stripes: total_stripes,
+                    }],
+                    Occupancy::SizeClass(_) => Vec::new(),
+                }),
+                pending_frees: (0..total_stripes.div_ceil(64))
+                    .map(|_| AtomicU64::new(0))
+                    .collect(),
+                has_pending_frees: AtomicBool::new(false),
+                free_stripes: AtomicUsize::new(free_stripes),
+                occupancy,
             }),
+        })
+    }
+
+    pub fn id(&self) -> usize {
+        self.data.id
+    }
+
+    /// Returns the number of stripes of each allocation if this slab only
+    /// serves a single size class.
+    pub fn size_class(&self) -> Option<usize> {
+        self.class().map(|class| class.stripes)
+    }
+
+    fn class(&self) -> Option<&SizeClass> {
+        match &self.data.occupancy {
+            Occupancy::SizeClass(class) => Some(class),
+            Occupancy::Spans { .. } => None,
         }
     }
 
-    pub fn allocate(&self, length: usize) -> Option<Allocation> {
-        struct BestSpan {
-            index: usize,
-            extra_stripes: usize,
+    /// Returns the size of this slab, in bytes.
+    pub fn size(&self) -> usize {
+        self.data.layout.size()
+    }
+
+    /// Allocates `length` bytes from this slab. Fails without waiting if
+    /// another thread is using this slab.
+    pub fn allocate(&self, length: usize) -> Result<Allocation, Unavailable> {
+        self.allocate_with(length, false)
+    }
+
+    /// Like [`Slab::allocate()`], but waits for another thread using this slab
+    /// instead of failing.
+    pub fn allocate_waiting(&self, length: usize) -> Option<Allocation> {
+        self.allocate_with(length, true).ok()
+    }
+
+    fn allocate_with(&self, length: usize, wait: bool) -> Result<Allocation, Unavailable> {
+        if length == 0 {
+            return Ok(Allocation::empty());
         }
+        let (strategy, cursor) = match &self.data.occupancy {
+            Occupancy::Spans { strategy, cursor } => (strategy, cursor),
+            Occupancy::SizeClass(class) => return self.allocate_slot(class, length),
+        };
         // To prevent a degree of fragmentation and provide interior alignment
         // guarantees, we're going to allocate in "stripes" of
         // minimum_allocation_size.
-        let stripes_needed =
-            (length + (self.data.minimum_allocation_size - 1)) / self.data.minimum_allocation_size;
+        let stripes_needed = length.div_ceil(self.data.minimum_allocation_size);
+        let stripes_reserved = stripes_needed + GUARD_STRIPES;
 
-        let mut free_spans = self.data.free_spans.try_lock()?;
-        let mut best_span = None;
-
-        // Find the span with the tightest fit.
-        for (index, span) in free_spans.iter().enumerate() {
-            if let Some(extra_stripes) = span.stripes.checked_sub(stripes_needed) {
-                if best_span.as_ref().map_or(true, |best_span: &BestSpan| {
-                    extra_stripes < best_span.extra_stripes
-                }) {
-                    best_span = Some(BestSpan {
-                        index,
-                        extra_stripes,
-                    });
-                    if extra_stripes == 0 {
-                        break;
+        let mut free_spans = if wait {
+            self.data.free_spans.lock()
+        } else {
+            match self.data.free_spans.try_lock() {
+                Some(free_spans) => free_spans,
+                None if self.data.free_stripes.load(Ordering::Relaxed) < stripes_reserved => {
+                    return Err(Unavailable::Full);
+                }
+                None => return Err(Unavailable::Contended),
+            }
+        };
+        self.merge_pending_frees(&mut free_spans);
+        let index = strategy
+            .select(
+                &free_spans,
+                stripes_reserved,
+                cursor.load(Ordering::Relaxed),
+            )
+            .filter(|index| {
+                free_spans
+                    .get(*index)
+                    .is_some_and(|span| span.stripes >= stripes_reserved)
+            })
+            .ok_or(Unavailable::Full)?;
+        let span = &mut free_spans[index];
+        span.stripes -= stripes_reserved;
+        self.data
+            .free_stripes
+            .fetch_sub(stripes_reserved, Ordering::Relaxed);
+        // SAFETY: span.offset will always be within the allocated range.
+        let bytes = unsafe { self.data.bytes.add(span.offset) };
+        let allocated_length = stripes_needed * self.data.minimum_allocation_size;
+        span.offset += stripes_reserved * self.data.minimum_allocation_size;
+        cursor.store(span.offset, Ordering::Relaxed);
+        if span.stripes == 0 {
+            free_spans.remove(index);
+        }
+        drop(free_spans);
+
+        Ok(self.prepare_allocation(bytes, allocated_length))
+    }
+
+    /// Claims the first free slot of this size class slab.
+    fn allocate_slot(&self, class: &SizeClass, length: usize) -> Result<Allocation, Unavailable> {
+        debug_assert!(length <= class.stripes * self.data.minimum_allocation_size);
+        if self.data.free_stripes.load(Ordering::Relaxed) < class.slot_stripes() {
+            return Err(Unavailable::Full);
+        }
+        let words = class.occupied.len();
+        let start = class.first_free.load(Ordering::Relaxed).min(words);
+        for index in (start..words).chain(0..start) {
+            let word = &class.occupied[index];
+            let mut bits = word.load(Ordering::Relaxed);
+            while bits != u64::MAX {
+                let bit = bits.trailing_ones() as usize;
+                match word.compare_exchange_weak(
+                    bits,
+                    bits | 1 << bit,
+                    Ordering::Acquire,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        self.data
+                            .free_stripes
+                            .fetch_sub(class.slot_stripes(), Ordering::Relaxed);
+                        let offset = (index * 64 + bit)
+                            * class.slot_stripes()
+                            * self.data.minimum_allocation_size;
+                        // SAFETY: Only slots within this slab have a clear bit.
+                        let bytes = unsafe { self.data.bytes.add(offset) };
+                        let allocated_length = class.stripes * self.data.minimum_allocation_size;
+                        return Ok(self.prepare_allocation(bytes, allocated_length));
                     }
+                    Err(current) => bits = current,
                 }
             }
+            // This word is full. Unless a slot was freed meanwhile, start the
+            // next search after it.
+            let _ = class.first_free.compare_exchange(
+                index,
+                index + 1,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            );
+        }
+        Err(Unavailable::Full)
+    }
+
+    /// Verifies, guards and scrubs the newly claimed memory at `bytes`.
+    fn prepare_allocation(&self, bytes: *mut u8, allocated_length: usize) -> Allocation {
+        self.verify_poison(bytes, allocated_length + self.guard_length());
+        // SAFETY: The guard stripe follows the allocated range.
+        self.write_guard(unsafe { bytes.add(allocated_length) });
+        self.scrub_allocated(bytes, allocated_length);
+        Allocation::slab(bytes, allocated_length, self.clone())
+    }
+
+    /// Returns the number of bytes in this slab that are not allocated.
+    pub fn free_bytes(&self) -> usize {
+        if let Some(class) = self.class() {
+            // The stripes after the last whole slot are never allocated, so
+            // they count as free.
+            let total_stripes = self.data.layout.size() / self.data.minimum_allocation_size;
+            let free_stripes = self.data.free_stripes.load(Ordering::Relaxed)
+                + total_stripes % class.slot_stripes();
+            return free_stripes * self.data.minimum_allocation_size;
         }
+        let mut free_spans = self.data.free_spans.lock();
+        self.merge_pending_frees(&mut free_spans);
+        free_spans
+            .iter()
+            .map(|span| span.stripes * self.data.minimum_allocation_size)
+            .sum()
+    }
 
-        if let Some(best_span) = best_span {
-            let span = &mut free_spans[best_span.index];
-            span.stripes -= stripes_needed;
-            // SAFETY: span.offset will always be within the allocated range.
-            let bytes = unsafe { self.data.bytes.add(span.offset) };
-            let allocated_length = stripes_needed * self.data.minimum_allocation_size;
-            span.offset += allocated_length;
-            if span.stripes == 0 {
-                free_spans.remove(best_span.index);
+    /// Attempts to grow the allocation at `allocation` to at least
+    /// `new_length` bytes by claiming the free span that immediately follows
+    /// it. Returns the new length of the allocation if successful. Size
+    /// class allocations never grow.
+    pub fn grow(&self, allocation: *mut u8, length: usize, new_length: usize) -> Option<usize> {
+        if self.class().is_some() {
+            return None;
+        }
+        let end = self.offset_of(allocation) + length + self.guard_length();
+        let additional_stripes = new_length
+            .div_ceil(self.data.minimum_allocation_size)
+            .checked_sub(length / self.data.minimum_allocation_size)?;
+
+        let mut free_spans = self.data.free_spans.try_lock()?;
+        self.merge_pending_frees(&mut free_spans);
+        let index = free_spans
+            .binary_search_by_key(&end, |span| span.offset)
+            .ok()?;
+        let span = &mut free_spans[index];
+        span.stripes = span.stripes.checked_sub(additional_stripes)?;
+        self.data
+            .free_stripes
+            .fetch_sub(additional_stripes, Ordering::Relaxed);
+        let additional_length = additional_stripes * self.data.minimum_allocation_size;
+        span.offset += additional_length;
+        if span.stripes == 0 {
+            free_spans.remove(index);
+        }
+        drop(free_spans);
+
+        // SAFETY: The claimed span starts at end, which is within the
+        // allocated range. The previous guard stripe becomes part of the
+        // allocation, and a new guard stripe follows the grown allocation.
+        unsafe {
+            self.verify_poison(self.data.bytes.add(end), additional_length);
+            self.verify_guard(allocation.add(length));
+            self.write_guard(allocation.add(length + additional_length));
+            self.scrub_allocated(allocation.add(length), additional_length);
+        }
+        Some(length + additional_length)
+    }
+
+    /// Returns the stripes at the end of the allocation at `allocation` that
+    /// are not needed to hold `new_length` bytes back to this slab. Returns
+    /// the new length of the allocation. Size class allocations never shrink.
+    pub fn shrink(&self, allocation: *mut u8, length: usize, new_length: usize) -> usize {
+        if self.class().is_some() {
+            return length;
+        }
+        let retained_length = new_length.div_ceil(self.data.minimum_allocation_size)
+            * self.data.minimum_allocation_size;
+        if retained_length < length {
+            // SAFETY: retained_length is less than length, so the resulting
+            // pointers are still within this allocation and its guard stripe.
+            unsafe {
+                self.verify_guard(allocation.add(length));
+                self.write_guard(allocation.add(retained_length));
+                self.free(
+                    allocation.add(retained_length + self.guard_length()),
+                    length - retained_length,
+                );
             }
-            Some(Allocation::slab(bytes, allocated_length, self.clone()))
+            retained_length
         } else {
-            None
+            length
+        }
+    }
+
+    /// Returns the whole stripes within the first `released_length` bytes of
+    /// the allocation at `allocation` back to this slab. At least one stripe
+    /// is always retained. Returns the number of bytes released, which is
+    /// always 0 for size class allocations.
+    pub fn release_front(
+        &self,
+        allocation: *mut u8,
+        length: usize,
+        released_length: usize,
+    ) -> usize {
+        if self.class().is_some() {
+            return 0;
+        }
+        let released_length = released_length.min(length.saturating_sub(1))
+            / self.data.minimum_allocation_size
+            * self.data.minimum_allocation_size;
+        self.free(allocation, released_length);
+        released_length
+    }
+
+    /// Releases the allocation at `allocation`, keeping it in the current
+    /// thread's cache if this slab uses one.
+    pub fn release(&self, allocation: *mut u8, length: usize) {
+        if let Some(cache) = &self.data.thread_cache {
+            // SAFETY: The guard stripe follows the allocation.
+            self.verify_guard(unsafe { allocation.add(length) });
+            self.scrub_freed(allocation, length);
+            if cache.put(self, allocation, length) {
+                return;
+            }
+        }
+        self.deallocate(allocation, length);
+    }
+
+    /// Returns an allocation that reuses the memory at `allocation`, which
+    /// was kept in a thread's cache after it was released.
+    pub fn reuse(&self, allocation: *mut u8, length: usize) -> Allocation {
+        self.verify_poison(allocation, length);
+        self.scrub_allocated(allocation, length);
+        Allocation::slab(allocation, length, self.clone())
+    }
+
+    /// Returns the allocation at `allocation`, which was kept in a thread's
+    /// cache after it was released, to this slab.
+    pub fn uncache(&self, allocation: *mut u8, length: usize) {
+        self.verify_poison(allocation, length);
+        self.deallocate(allocation, length);
+    }
+
+    /// Returns the allocation at `allocation` and its guard stripe to this
+    /// slab.
+    pub fn deallocate(&self, allocation: *mut u8, length: usize) {
+        // SAFETY: The guard stripe follows the allocation.
+        self.verify_guard(unsafe { allocation.add(length) });
+        self.free(allocation, length + self.guard_length());
+    }
+
+    fn guard_length(&self) -> usize {
+        GUARD_STRIPES * self.data.minimum_allocation_size
+    }
+
+    /// Zeroes newly allocated memory if required by the scrub policy. When
+    /// debug checks are enabled, freed memory is poisoned rather than zeroed,
+    /// so it is zeroed upon allocation instead.
+    fn scrub_allocated(&self, bytes: *mut u8, length: usize) {
+        if self.data.scrub == ScrubPolicy::ZeroOnAllocate
+            || (DEBUG_CHECKS && self.data.scrub == ScrubPolicy::ZeroOnFree)
+        {
+            self.zero(bytes, length);
+        }
+    }
+
+    /// Poisons freed memory when debug checks are enabled, or zeroes it if
+    /// required by the scrub policy.
+    fn scrub_freed(&self, bytes: *mut u8, length: usize) {
+        if DEBUG_CHECKS {
+            // SAFETY: bytes..bytes + length is always a range of stripes
+            // within this slab that is no longer used by any allocation.
+            unsafe { bytes.write_bytes(POISON, length) };
+        } else if self.data.scrub == ScrubPolicy::ZeroOnFree {
+            self.zero(bytes, length);
         }
     }
 
-    pub fn free(&self, allocation: *mut u8, length: usize) {
+    fn verify_poison(&self, bytes: *mut u8, length: usize) {
+        if DEBUG_CHECKS {
+            let offset = self.offset_of(bytes);
+            // SAFETY: bytes..bytes + length is always a range of stripes
+            // within this slab that is owned by the caller.
+            let memory = unsafe { slice::from_raw_parts(bytes, length) };
+            if let Some(modified) = memory.iter().position(|byte| *byte != POISON) {
+                panic!(
+                    "slab {}: freed memory at offset {} was written to after being freed",
+                    self.data.id,
+                    offset + modified
+                );
+            }
+        }
+    }
+
+    fn write_guard(&self, guard: *mut u8) {
+        if DEBUG_CHECKS {
+            debug_assert!(self.offset_of(guard) + self.guard_length() <= self.data.layout.size());
+            // SAFETY: The guard stripe is always within this slab and is
+            // reserved alongside the allocation it follows.
+            unsafe { guard.write_bytes(CANARY, self.guard_length()) };
+        }
+    }
+
+    fn verify_guard(&self, guard: *mut u8) {
+        if DEBUG_CHECKS {
+            let guard_offset = self.offset_of(guard);
+            // SAFETY: The guard stripe is always within this slab and is
+            // reserved alongside the allocation it follows.
+            let memory = unsafe { slice::from_raw_parts(guard, self.guard_length()) };
+            if let Some(modified) = memory.iter().position(|byte| *byte != CANARY) {
+                panic!(
+                    "slab {}: allocation overflowed into its guard stripe at offset {}",
+                    self.data.id,
+                    guard_offset + modified
+                );
+            }
+        }
+    }
+
+    fn zero(&self, bytes: *mut u8, length: usize) {
+        debug_assert!(self.offset_of(bytes) + length <= self.data.layout.size());
+        // SAFETY: bytes..bytes + length is always a range of stripes within
+        // this slab that is owned by the caller.
+        unsafe { bytes.write_bytes(0, length) };
+    }
+
+    fn offset_of(&self, allocation: *mut u8) -> usize {
         // SAFETY: This is an internal type, and this function can only be
         // called from this crate. It is only called with `allocation` being
         // from the same slab, as a reference to the clone when the allocation
         // was created is used to call this function. As such, allocation must
         // lie within the allocated range of self.data.bytes.
-        let offset = usize::try_from(unsafe { allocation.offset_from(self.data.bytes) })
-            .expect("invalid allocation pointer");
-        let freed_span = Span {
+        usize::try_from(unsafe { allocation.offset_from(self.data.bytes) })
+            .expect("invalid allocation pointer")
+    }
+
+    fn free(&self, allocation: *mut u8, length: usize) {
+        let offset = self.offset_of(allocation);
+        let freed_span = FreeSpan {
             offset,
             stripes: length / self.data.minimum_allocation_size,
         };
-        let mut free_spans = self.data.free_spans.lock();
+        if freed_span.stripes == 0 {
+            return;
+        }
+        self.data
+            .free_stripes
+            .fetch_add(freed_span.stripes, Ordering::Relaxed);
+        self.scrub_freed(allocation, length);
+        if let Some(class) = self.class() {
+            let slot = offset / (class.slot_stripes() * self.data.minimum_allocation_size);
+            class.occupied[slot / 64].fetch_and(!(1 << (slot % 64)), Ordering::Release);
+            class.first_free.fetch_min(slot / 64, Ordering::Relaxed);
+        } else if let Some(mut free_spans) = self.data.free_spans.try_lock() {
+            self.merge_pending_frees(&mut free_spans);
+            self.insert_free_span(&mut free_spans, freed_span);
+        } else {
+            self.mark_pending_free(&freed_span);
+        }
+        self.data.notifier.notify_freed();
+    }
+
+    /// Sets the pending free bits of the stripes in `span`.
+    fn mark_pending_free(&self, span: &FreeSpan) {
+        let first = span.offset / self.data.minimum_allocation_size;
+        let end = first + span.stripes;
+        let mut stripe = first;
+        while stripe < end {
+            let bit = stripe % 64;
+            let bits = (end - stripe).min(64 - bit);
+            let mask = if bits == 64 {
+                u64::MAX
+            } else {
+                ((1 << bits) - 1) << bit
+            };
+            self.data.pending_frees[stripe / 64].fetch_or(mask, Ordering::Release);
+            stripe += bits;
+        }
+        self.data.has_pending_frees.store(true, Ordering::Release);
+    }
+
+    /// Moves all pending frees into `free_spans`, merging adjacent stripes.
+    fn merge_pending_frees(&self, free_spans: &mut Vec<FreeSpan>) {
+        if !self.data.has_pending_frees.load(Ordering::Relaxed)
+            || !self.data.has_pending_frees.swap(false, Ordering::Acquire)
+        {
+            return;
+        }
+
+        let mut run: Option<FreeSpan> = None;
+        for (index, word) in self.data.pending_frees.iter().enumerate() {
+            let bits = if word.load(Ordering::Relaxed) == 0 {
+                0
+            } else {
+                word.swap(0, Ordering::Acquire)
+            };
+            let mut bit = 0;
+            while bit < 64 {
+                let remaining = bits >> bit;
+                if remaining == 0 {
+                    break;
+                }
+                let zeros = remaining.trailing_zeros() as usize;
+                if zeros > 0 {
+                    if let Some(span) = run.take() {
+                        self.insert_free_span(free_spans, span);
+                    }
+                    bit += zeros;
+                }
+                let ones = (bits >> bit).trailing_ones() as usize;
+                let offset = (index * 64 + bit) * self.data.minimum_allocation_size;
+                match &mut run {
+                    Some(span) if span.end(self.data.minimum_allocation_size) == offset => {
+                        span.stripes += ones;
+                    }
+                    _ => {
+                        if let Some(span) = run.replace(FreeSpan {
+                            offset,
+                            stripes: ones,
+                        }) {
+                            self.insert_free_span(free_spans, span);
+                        }
+                    }
+                }
+                bit += ones;
+            }
+            if bit < 64 {
+                // The run was followed by a stripe that is not pending.
+                if let Some(span) = run.take() {
+                    self.insert_free_span(free_spans, span);
+                }
+            }
+        }
+        if let Some(span) = run {
+            self.insert_free_span(free_spans, span);
+        }
+    }
 
+    fn insert_free_span(&self, free_spans: &mut Vec<FreeSpan>, freed_span: FreeSpan) {
         for (index, span) in free_spans.iter_mut().enumerate() {
             if span.offset < freed_span.offset
                 && span.end(self.data.minimum_allocation_size) == freed_span.offset
@@ -116,7 +688,7 @@ impl Slab {
                 // We can merge.
                 span.stripes += freed_span.stripes;
                 let new_end = span.end(self.data.minimum_allocation_size);
-                Self::merge_next_span_if_possible(&mut free_spans, index, new_end);
+                Self::merge_next_span_if_possible(free_spans, index, new_end);
                 return;
             } else if freed_span.offset < span.offset {
                 if span.offset == freed_span.end(self.data.minimum_allocation_size) {
@@ -124,7 +696,7 @@ impl Slab {
                     span.offset = freed_span.offset;
                     span.stripes += freed_span.stripes;
                     let new_end = span.end(self.data.minimum_allocation_size);
-                    Self::merge_next_span_if_possible(&mut free_spans, index, new_end);
+                    Self::merge_next_span_if_possible(free_spans, index, new_end);
                     return;
                 }
 
@@ -138,7 +710,7 @@ impl Slab {
         free_spans.push(freed_span);
     }
 
-    fn merge_next_span_if_possible(free_spans: &mut Vec<Span>, index: usize, new_end: usize) {
+    fn merge_next_span_if_possible(free_spans: &mut Vec<FreeSpan>, index: usize, new_end: usize) {
         if let Some(next_span) = free_spans.get(index + 1) {
             if next_span.offset == new_end {
                 // The freed span can just extend the next entry
@@ -161,30 +733,139 @@ impl Drop for Data {
     }
 }
 
+/// Controls whether slab memory is zeroed as it is reused, preventing an
+/// allocation from observing the contents of a previous allocation.
+///
+/// Memory is always zeroed when a slab is first allocated.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default)]
+pub enum ScrubPolicy {
+    /// Memory is not zeroed when it is reused.
+    #[default]
+    Off,
+    /// Memory is zeroed when it is returned to the slab.
+    ZeroOnFree,
+    /// Memory is zeroed when it is allocated from the slab.
+    ZeroOnAllocate,
+}
+
+/// Controls how the slabs of an [`Allocator`](crate::Allocator) are divided
+/// into allocations.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default)]
+pub enum SlabLayout {
+    /// Each slab serves allocations of any size, placing each allocation in
+    /// a free span chosen by the
+    /// [placement strategy](crate::Config::placement_strategy), which is
+    /// [`BestFit`](crate::BestFit) by default. Allocations can grow and
+    /// shrink in place.
+    #[default]
+    Mixed,
+    /// Each slab serves allocations of a single size class, tracking which
+    /// slots are allocated with a bitmap. Allocating and freeing never wait
+    /// for other threads and do not fragment the slab, but allocations are
+    /// rounded up to their size class and never grow or shrink in place.
+    ///
+    /// Size classes are spaced four per doubling of the number of stripes,
+    /// so rounding wastes at most 25% of an allocation.
+    SizeClasses,
+}
+
+/// The reason a slab could not serve an allocation.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Unavailable {
+    /// The slab has no free span large enough.
+    Full,
+    /// Another thread was using the slab.
+    Contended,
+}
+
+/// A range of free stripes within a slab, as seen by a
+/// [`PlacementStrategy`].
 #[derive(Debug, Clone, Copy)]
-struct Span {
+pub struct FreeSpan {
     offset: usize,
     stripes: usize,
 }
 
-impl Span {
+impl FreeSpan {
+    /// Returns the offset of this span from the start of its slab, in bytes.
+    #[must_use]
+    pub const fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Returns the number of stripes in this span.
+    #[must_use]
+    pub const fn stripes(&self) -> usize {
+        self.stripes
+    }
+
     const fn end(&self, minimum_allocation_size: usize) -> usize {
         self.offset + self.stripes * minimum_allocation_size
     }
 }
 
+/// The length of the guard stripe that follows each allocation from a test
+/// slab.
+#[cfg(test)]
+const TEST_GUARD_LENGTH: usize = GUARD_STRIPES * 16;
+
+/// Returns a slab of `size` bytes with 16 byte stripes for tests.
+#[cfg(test)]
+fn test_slab(size: usize, placement: Placement) -> Slab {
+    scrubbed_test_slab(size, ScrubPolicy::Off, placement)
+}
+
+/// Returns a slab of `size` bytes with 16 byte stripes that caches freed
+/// allocations for `owner`.
+#[cfg(test)]
+fn cached_test_slab(size: usize, owner: &Arc<dyn Send + Sync>) -> Slab {
+    let cache = ThreadCache::new(
+        Arc::downgrade(owner),
+        2,
+        Arc::new(AtomicUsize::new(size)),
+        16,
+    );
+    Slab::new(
+        size,
+        Layout::array::<u8>(size).unwrap(),
+        16,
+        ScrubPolicy::Off,
+        Arc::default(),
+        cache,
+        Placement::default(),
+    )
+    .unwrap()
+}
+
+#[cfg(test)]
+fn scrubbed_test_slab(size: usize, scrub: ScrubPolicy, placement: Placement) -> Slab {
+    Slab::new(
+        size,
+        Layout::array::<u8>(size).unwrap(),
+        16,
+        scrub,
+        Arc::default(),
+        None,
+        placement,
+    )
+    .unwrap()
+}
+
 #[test]
 fn basic_tests() {
-    let slab = Slab::new(64, Layout::array::<u8>(64).unwrap(), 16);
+    let slab = test_slab(4 * (16 + TEST_GUARD_LENGTH), Placement::default());
 
     // We should be able to allocate 4 blocks. Each should be rounded up to 16
-    // bytes, our minimum allocation length.
+    // bytes, our minimum allocation length, and followed by its guard stripe.
     let alloc1 = slab.allocate(1).unwrap();
     let alloc2 = slab.allocate(2).unwrap();
     let alloc3 = slab.allocate(8).unwrap();
     let alloc4 = slab.allocate(16).unwrap();
 
-    assert!(slab.allocate(16).is_none(), "slab should be full");
+    assert!(
+        matches!(slab.allocate(16), Err(Unavailable::Full)),
+        "slab should be full"
+    );
 
     // Free and reallocate
     drop(alloc1);
@@ -195,14 +876,247 @@ fn basic_tests() {
     drop(alloc4);
     drop(alloc3);
 
-    // Now we should be able to allocate 48 bytes.
-    let alloc2 = slab.allocate(48).unwrap();
+    // Now we should be able to allocate the three contiguous blocks at once.
+    let alloc2 = slab.allocate(48 + 2 * TEST_GUARD_LENGTH).unwrap();
 
     // Free everything
     drop(alloc2);
     drop(alloc1);
 
     // allocate the entire slab
-    let alloc1 = slab.allocate(64).unwrap();
+    let alloc1 = slab.allocate(64 + 3 * TEST_GUARD_LENGTH).unwrap();
+    drop(alloc1);
+}
+
+#[test]
+fn grow_in_place() {
+    let slab = test_slab(64 + TEST_GUARD_LENGTH, Placement::default());
+
+    let mut alloc1 = slab.allocate(16).unwrap();
+    assert!(alloc1.try_grow_in_place(30));
+    assert_eq!(alloc1.len(), 32);
+
+    // Block further growth by allocating the next stripe.
+    let alloc2 = slab.allocate(16).unwrap();
+    assert!(!alloc1.try_grow_in_place(48));
+
+    // Once the next stripe is freed, it merges with the remaining free space
+    // and can be claimed.
+    drop(alloc2);
+    assert!(alloc1.try_grow_in_place(64));
+    assert_eq!(alloc1.len(), 64);
+    assert!(slab.allocate(1).is_err(), "slab should be full");
+}
+
+#[test]
+fn shrink_in_place() {
+    let slab = test_slab(64 + 2 * TEST_GUARD_LENGTH, Placement::default());
+
+    let mut alloc1 = slab.allocate(64).unwrap();
+    alloc1.as_slice_mut()[..4].copy_from_slice(b"rust");
+    let address = alloc1.address();
+    alloc1.shrink_to(20);
+    assert_eq!(alloc1.len(), 32);
+    assert_eq!(alloc1.address(), address);
+    assert_eq!(&alloc1.as_slice()[..4], b"rust");
+
+    // The released stripes can be allocated again.
+    let alloc2 = slab.allocate(32).unwrap();
+    assert!(slab.allocate(1).is_err(), "slab should be full");
+    drop(alloc2);
     drop(alloc1);
+    let alloc1 = slab.allocate(64 + TEST_GUARD_LENGTH).unwrap();
+    drop(alloc1);
+}
+
+#[test]
+fn release_front() {
+    let slab = test_slab(64 + TEST_GUARD_LENGTH, Placement::default());
+
+    let mut alloc1 = slab.allocate(64).unwrap();
+    let address = alloc1.address();
+    assert_eq!(alloc1.release_front(40), 32);
+    assert_eq!(alloc1.len(), 32);
+    assert_eq!(alloc1.address(), address.wrapping_add(32));
+
+    // The released stripes can be allocated again, including the guard
+    // stripe that follows the new allocation.
+    let alloc2 = slab.allocate(32 - TEST_GUARD_LENGTH).unwrap();
+    assert_eq!(alloc2.address(), address);
+    assert!(slab.allocate(1).is_err(), "slab should be full");
+
+    // The final stripe is never released.
+    assert_eq!(alloc1.release_front(32), 16);
+    assert_eq!(alloc1.release_front(16), 0);
+}
+
+#[test]
+fn scrubbing() {
+    for scrub in [ScrubPolicy::ZeroOnFree, ScrubPolicy::ZeroOnAllocate] {
+        let slab = scrubbed_test_slab(128, scrub, Placement::default());
+
+        let mut alloc1 = slab.allocate(32).unwrap();
+        alloc1.as_slice_mut().fill(0xFF);
+        alloc1.shrink_to(16);
+        drop(alloc1);
+        let alloc1 = slab.allocate(64).unwrap();
+        assert!(alloc1.as_slice().iter().all(|byte| *byte == 0));
+    }
+}
+
+#[test]
+#[cfg(feature = "debug-checks")]
+#[should_panic = "allocation overflowed into its guard stripe at offset 16"]
+fn guard_overflow() {
+    let slab = test_slab(64, Placement::default());
+    let allocation = slab.allocate(16).unwrap();
+    // SAFETY: The guard stripe is within the slab.
+    unsafe { allocation.address().add(16).write(0) };
+    drop(allocation);
+}
+
+#[test]
+#[cfg(feature = "debug-checks")]
+#[should_panic = "freed memory at offset 4 was written to after being freed"]
+fn use_after_free() {
+    let slab = test_slab(64, Placement::default());
+    let allocation = slab.allocate(16).unwrap();
+    let address = allocation.address();
+    drop(allocation);
+    // SAFETY: The freed memory is still within the slab.
+    unsafe { address.add(4).write(0) };
+    let _ = slab.allocate(16);
+}
+
+#[test]
+fn pending_frees() {
+    let slab = test_slab(4096, Placement::default());
+    let allocations = (0..20)
+        .map(|_| slab.allocate(100).unwrap())
+        .collect::<Vec<_>>();
+
+    // While another thread holds the lock, freed stripes are left pending.
+    let free_spans = slab.data.free_spans.lock();
+    assert_eq!(slab.allocate(16).unwrap_err(), Unavailable::Contended);
+    drop(allocations);
+    assert!(slab.data.has_pending_frees.load(Ordering::Relaxed));
+    assert_eq!(free_spans.len(), 1);
+    drop(free_spans);
+
+    assert_eq!(slab.free_bytes(), 4096);
+    assert_eq!(slab.data.free_spans.lock().len(), 1);
+
+    let threads = (0..4)
+        .map(|_| {
+            let slab = slab.clone();
+            std::thread::spawn(move || {
+                for length in 1..500 {
+                    if let Ok(mut allocation) = slab.allocate(length) {
+                        allocation.as_slice_mut().fill(1);
+                    }
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+    assert_eq!(slab.free_bytes(), 4096);
+    assert_eq!(slab.data.free_spans.lock().len(), 1);
+}
+
+#[test]
+fn placement_strategies() {
+    use crate::placement::{FirstFit, NextFit};
+
+    let strategies: [(Arc<dyn PlacementStrategy>, usize); 3] = [
+        (Arc::new(BestFit), 48 + 2 * TEST_GUARD_LENGTH),
+        (Arc::new(FirstFit), 0),
+        (Arc::new(NextFit), 80 + 4 * TEST_GUARD_LENGTH),
+    ];
+    for (strategy, expected_offset) in strategies {
+        let slab = test_slab(128 + 5 * TEST_GUARD_LENGTH, Placement::Spans(strategy));
+        let alloc1 = slab.allocate(32).unwrap();
+        let start = alloc1.address() as usize;
+        let _alloc2 = slab.allocate(16).unwrap();
+        let alloc3 = slab.allocate(16).unwrap();
+        let _alloc4 = slab.allocate(16).unwrap();
+        // Without guard stripes, free spans of 2, 1 and 3 stripes remain, and
+        // the previous allocation ended at offset 80.
+        drop(alloc1);
+        drop(alloc3);
+        let placed = slab.allocate(16).unwrap();
+        assert_eq!(placed.address() as usize - start, expected_offset);
+    }
+}
+
+#[test]
+fn size_classes() {
+    let slot_length = (3 + GUARD_STRIPES) * 16;
+    let slots = 4096 / slot_length;
+    let slab = test_slab(4096, Placement::SizeClass(3));
+    assert_eq!(slab.size_class(), Some(3));
+    let mut allocations = (0..slots)
+        .map(|_| slab.allocate(40).unwrap())
+        .collect::<Vec<_>>();
+    assert!(matches!(slab.allocate(1), Err(Unavailable::Full)));
+    let unusable = 4096 - slots * slot_length;
+    assert_eq!(slab.free_bytes(), unusable);
+    // Searches skip the words that were full.
+    let first_free = &slab.class().unwrap().first_free;
+    assert_eq!(first_free.load(Ordering::Relaxed), (slots - 1) / 64);
+
+    // Size class allocations keep their length.
+    let allocation = &mut allocations[1];
+    assert_eq!(allocation.len(), 48);
+    assert!(!allocation.try_grow_in_place(64));
+    allocation.shrink_to(16);
+    assert_eq!(allocation.release_front(16), 0);
+    assert_eq!(allocation.len(), 48);
+
+    // Freed slots are reused.
+    let address = allocation.address();
+    drop(allocations.swap_remove(1));
+    assert_eq!(slab.free_bytes(), unusable + slot_length);
+    assert_eq!(first_free.load(Ordering::Relaxed), 0);
+    let reused = slab.allocate(33).unwrap();
+    assert_eq!(reused.address(), address);
+    drop(reused);
+    drop(allocations);
+    assert_eq!(slab.free_bytes(), 4096);
+}
+
+#[test]
+fn thread_cache_outlived() {
+    let owner: Arc<dyn Send + Sync> = Arc::new(());
+    let slab = cached_test_slab(1024, &owner);
+    let data = Arc::downgrade(&slab.data);
+    let cached = slab.allocate(16).unwrap();
+    let live = slab.allocate(16).unwrap();
+    drop((cached, slab));
+    // Once its owner is gone, nothing more is cached, but the slab is still
+    // held by the thread's cache.
+    drop(owner);
+    drop(live);
+    assert!(data.upgrade().is_some());
+    // Using any thread cache releases the caches of dropped owners.
+    let other: Arc<dyn Send + Sync> = Arc::new(());
+    let other_slab = cached_test_slab(1024, &other);
+    let other_cache = other_slab.data.thread_cache.as_ref().unwrap();
+    assert!(other_cache.take(16).is_none());
+    assert!(data.upgrade().is_none());
+}
+
+#[test]
+#[cfg(feature = "debug-checks")]
+#[should_panic = "freed memory at offset 4 was written to after being freed"]
+fn cached_use_after_free() {
+    let owner: Arc<dyn Send + Sync> = Arc::new(());
+    let slab = cached_test_slab(1024, &owner);
+    let allocation = slab.allocate(16).unwrap();
+    let address = allocation.address();
+    drop(allocation);
+    // SAFETY: The cached memory is still within the slab.
+    unsafe { address.add(4).write(0) };
+    let _ = slab.data.thread_cache.as_ref().unwrap().take(16);
 }
@@ -0,0 +1,87 @@
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    OnceLock,
+};
+
+use crate::slab::Slab;
+
+/// The number of slabs in the first bucket. Each bucket holds twice as many
+/// slabs as the previous one.
+const FIRST_BUCKET: usize = 8;
+const BUCKETS: usize = (usize::BITS - FIRST_BUCKET.trailing_zeros()) as usize;
+
+/// The slabs of a ring.
+///
+/// Slabs are only ever appended, and are stored in buckets that never move
+/// once allocated. Reading the slabs does not lock or update any reference
+/// count: readers load the length, then each slab they visit.
+#[derive(Debug)]
+pub struct SlabList {
+    buckets: [OnceLock<Box<[OnceLock<Slab>]>>; BUCKETS],
+    len: AtomicUsize,
+}
+
+impl Default for SlabList {
+    fn default() -> Self {
+        Self {
+            buckets: [const { OnceLock::new() }; BUCKETS],
+            len: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl SlabList {
+    /// Returns the number of slabs that have been pushed.
+    pub fn len(&self) -> usize {
+        self.len.load(Ordering::Acquire)
+    }
+
+    /// Returns the slab at `index`, if it has been pushed.
+    pub fn get(&self, index: usize) -> Option<&Slab> {
+        let (bucket, offset) = Self::locate(index);
+        self.buckets[bucket].get()?.get(offset)?.get()
+    }
+
+    /// Appends `slab` and returns its index along with the slab. Pushes must
+    /// not race each other.
+    pub fn push(&self, slab: Slab) -> (usize, &Slab) {
+        let index = self.len.load(Ordering::Relaxed);
+        let (bucket, offset) = Self::locate(index);
+        let slots = self.buckets[bucket].get_or_init(|| {
+            (0..FIRST_BUCKET << bucket)
+                .map(|_| OnceLock::new())
+                .collect()
+        });
+        let Ok(()) = slots[offset].set(slab) else {
+            panic!("slabs were pushed concurrently");
+        };
+        // Publishing the new length makes the slab visible to readers.
+        self.len.store(index + 1, Ordering::Release);
+        (index, slots[offset].get().expect("the slab was just set"))
+    }
+
+    /// Returns an iterator over the slabs pushed so far.
+    pub fn iter(&self) -> impl Iterator<Item = &Slab> {
+        (0..self.len()).filter_map(|index| self.get(index))
+    }
+
+    /// Returns the bucket and the offset within it of the slab at `index`.
+    fn locate(index: usize) -> (usize, usize) {
+        let position = index + FIRST_BUCKET;
+        let bucket = (position.ilog2() - FIRST_BUCKET.ilog2()) as usize;
+        (bucket, position - (FIRST_BUCKET << bucket))
+    }
+}
+
+#[test]
+fn locations() {
+    assert_eq!(SlabList::locate(0), (0, 0));
+    assert_eq!(SlabList::locate(7), (0, 7));
+    assert_eq!(SlabList::locate(8), (1, 0));
+    assert_eq!(SlabList::locate(23), (1, 15));
+    assert_eq!(SlabList::locate(24), (2, 0));
+    assert_eq!(
+        SlabList::locate(usize::MAX - FIRST_BUCKET),
+        (BUCKETS - 1, (FIRST_BUCKET << (BUCKETS - 1)) - 1)
+    );
+}
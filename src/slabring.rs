@@ -1,14 +1,90 @@
 use std::{
     alloc::{self, Layout},
+    cell::Cell,
+    future::Future,
+    pin::Pin,
     sync::{
         atomic::{AtomicUsize, Ordering},
-        Arc,
+        Arc, Weak,
     },
+    task::{Context, Poll},
+    thread,
+    time::Instant,
 };
 
-use parking_lot::{RwLock, RwLockReadGuard};
+use parking_lot::Mutex;
 
-use crate::{slab::Slab, Allocation, Config};
+use crate::{
+    notify::MemoryNotifier,
+    slab::{size_class, Placement, Slab, SlabLayout, Unavailable, GUARD_STRIPES},
+    slablist::SlabList,
+    threadcache::ThreadCache,
+    AllocError, Allocation, Config, ConfigError, Statistics,
+};
+
+/// Controls what an [`Allocator`](crate::Allocator) does when an allocation
+/// cannot be served without exceeding its
+/// [memory limit](crate::Config::memory_limit).
+///
+/// Has no effect unless a memory limit is configured. When the limit is
+/// enforced using [`LimitPolicy::Error`] or [`LimitPolicy::Block`] and only
+/// covers [slabs](LimitScope::Slabs), allocations that are too large to be
+/// made from a slab fail with [`AllocError::MemoryLimitExceeded`].
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default)]
+pub enum LimitPolicy {
+    /// The allocation is made using the global allocator.
+    #[default]
+    FallbackToGlobal,
+    /// The allocation fails with [`AllocError::MemoryLimitExceeded`].
+    Error,
+    /// The calling thread blocks until enough memory is freed by other
+    /// threads.
+    Block,
+}
+
+/// Controls which memory counts towards an [`Allocator`](crate::Allocator)'s
+/// [memory limit](crate::Config::memory_limit).
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default)]
+pub enum LimitScope {
+    /// Only the memory dedicated to slabs counts towards the limit.
+    #[default]
+    Slabs,
+    /// The memory dedicated to slabs and the memory of allocations made using
+    /// the global allocator count towards the limit.
+    Total,
+}
+
+/// The result of attempting to allocate without waiting.
+enum Attempt {
+    Allocated(Allocation),
+    AtLimit,
+}
+
+/// The result of attempting to add a slab.
+enum NewSlab<'a> {
+    Created(usize, &'a Slab),
+    Raced,
+    AtLimit,
+    /// The slab size was lowered after the allocation was found to fit.
+    TooSmall,
+}
+
+/// The number of times slabs that were in use by other threads are retried
+/// before a new slab is added.
+const CONTENDED_RETRIES: usize = 2;
+
+/// The number of rings whose home slab each thread remembers.
+const HOME_SLAB_ENTRIES: usize = 8;
+
+static NEXT_RING_ID: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    /// The id of a ring and the index of the slab the current thread last
+    /// allocated from in it, in the entry for the ring's id. Slabs are never
+    /// removed from a ring, so the index remains valid.
+    static HOME_SLABS: [Cell<Option<(usize, usize)>>; HOME_SLAB_ENTRIES] =
+        const { [const { Cell::new(None) }; HOME_SLAB_ENTRIES] };
+}
 
 #[derive(Clone, Debug)]
 pub struct SlabRing {
@@ -17,134 +93,527 @@ pub struct SlabRing {
 
 #[derive(Debug)]
 struct Data {
-    entries: RwLock<Vec<Slab>>,
+    id: usize,
+    slabs: SlabList,
+    /// Serializes adding slabs.
+    growing: Mutex<()>,
     cycle: AtomicUsize,
-    layout: Layout,
+    notifier: Arc<MemoryNotifier>,
+    thread_cache: Option<ThreadCache>,
+    /// The current memory limit, or 0 if there is no limit. Along with
+    /// `slab_size` and `maximum_allocation_size`, this may be changed after
+    /// the ring is created, and supersedes the value in `config`.
+    memory_limit: AtomicUsize,
+    /// Shared with the thread cache, which caches at most one slab's worth
+    /// of memory per thread.
+    slab_size: Arc<AtomicUsize>,
+    maximum_allocation_size: AtomicUsize,
+    /// Serializes changes to the adjustable settings.
+    reconfiguring: Mutex<()>,
+    /// The number of slabs added while other slabs were in use by other
+    /// threads and may have had room.
+    contended_slabs: AtomicUsize,
+    /// The bytes dedicated to slabs plus the bytes of global allocations.
+    total_bytes: AtomicUsize,
+    global_bytes: AtomicUsize,
+    global_allocations: AtomicUsize,
     config: Config,
 }
 
 impl SlabRing {
     pub fn new(config: Config) -> Result<Self, alloc::LayoutError> {
-        let layout = Layout::array::<u8>(config.slab_size)?;
+        Layout::array::<u8>(config.slab_size)?;
+        let slab_size = Arc::new(AtomicUsize::new(config.slab_size));
         Ok(Self {
-            data: Arc::new(Data {
-                entries: RwLock::default(),
+            data: Arc::new_cyclic(|data: &Weak<Data>| Data {
+                id: NEXT_RING_ID.fetch_add(1, Ordering::Relaxed),
+                slabs: SlabList::default(),
+                growing: Mutex::default(),
                 cycle: AtomicUsize::default(),
-                layout,
+                notifier: Arc::new(MemoryNotifier::new(config.memory_limit.is_some())),
+                thread_cache: ThreadCache::new(
+                    data.clone(),
+                    config.thread_cache_capacity,
+                    slab_size.clone(),
+                    config.minimum_allocation_size,
+                ),
+                memory_limit: AtomicUsize::new(config.memory_limit.unwrap_or_default()),
+                slab_size,
+                maximum_allocation_size: AtomicUsize::new(config.maximum_allocation_size),
+                reconfiguring: Mutex::default(),
+                contended_slabs: AtomicUsize::default(),
+                total_bytes: AtomicUsize::default(),
+                global_bytes: AtomicUsize::default(),
+                global_allocations: AtomicUsize::default(),
                 config,
             }),
         })
     }
 
-    pub fn allocate(&self, length: usize) -> Option<Allocation> {
-        if length < self.data.config.maximum_allocation_size {
-            // Try to allocate in all existing slabs.
-            for slab in self.iter() {
-                if let Some(allocation) = slab.allocate(length) {
-                    return Some(allocation);
+    /// Returns the configuration this ring was created with. The adjustable
+    /// settings may have changed since.
+    pub fn config(&self) -> &Config {
+        &self.data.config
+    }
+
+    pub fn memory_limit(&self) -> Option<usize> {
+        match self.data.memory_limit.load(Ordering::Relaxed) {
+            0 => None,
+            limit => Some(limit),
+        }
+    }
+
+    fn slab_size(&self) -> usize {
+        self.data.slab_size.load(Ordering::Relaxed)
+    }
+
+    fn maximum_allocation_size(&self) -> usize {
+        self.data.maximum_allocation_size.load(Ordering::Relaxed)
+    }
+
+    /// Applies `change` to the adjustable settings of this ring's
+    /// configuration: the memory limit, slab size and maximum allocation
+    /// size. Existing slabs are not affected.
+    pub fn reconfigure(&self, change: impl FnOnce(&mut Config)) -> Result<(), ConfigError> {
+        let _guard = self.data.reconfiguring.lock();
+        let mut config = self.data.config.clone();
+        config.memory_limit = self.memory_limit();
+        config.slab_size = self.slab_size();
+        config.maximum_allocation_size = self.maximum_allocation_size();
+        change(&mut config);
+        config.validate()?;
+        Layout::array::<u8>(config.slab_size)?;
+
+        self.data
+            .memory_limit
+            .store(config.memory_limit.unwrap_or_default(), Ordering::Relaxed);
+        self.data
+            .slab_size
+            .store(config.slab_size, Ordering::Relaxed);
+        self.data
+            .maximum_allocation_size
+            .store(config.maximum_allocation_size, Ordering::Relaxed);
+        self.data
+            .notifier
+            .set_limited(config.memory_limit.is_some());
+        // Raising or removing the limit may allow waiting allocations to
+        // proceed.
+        self.data.notifier.notify();
+        Ok(())
+    }
+
+    /// Allocates `length` bytes, following the configured limit policy.
+    pub fn allocate(&self, length: usize) -> Result<Allocation, AllocError> {
+        self.allocate_with(length, self.data.config.limit_policy, None)
+    }
+
+    /// Allocates `length` bytes, following `policy` when the memory limit has
+    /// been reached. When blocking, gives up at `deadline`.
+    pub fn allocate_with(
+        &self,
+        length: usize,
+        policy: LimitPolicy,
+        deadline: Option<Instant>,
+    ) -> Result<Allocation, AllocError> {
+        loop {
+            let generation = self.data.notifier.generation();
+            match self.attempt(length, policy)? {
+                Attempt::Allocated(allocation) => return Ok(allocation),
+                Attempt::AtLimit => {
+                    if policy == LimitPolicy::Error
+                        || !self.data.notifier.wait(generation, deadline)
+                    {
+                        return Err(self.limit_exceeded());
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns a future that allocates `length` bytes, waiting for memory to
+    /// be freed if the memory limit has been reached.
+    pub fn allocate_async(&self, length: usize) -> PendingAllocation<'_> {
+        PendingAllocation {
+            ring: self,
+            length,
+            registration: None,
+        }
+    }
+
+    /// Attempts to allocate `length` bytes without waiting for memory to be
+    /// freed.
+    fn attempt(&self, length: usize, policy: LimitPolicy) -> Result<Attempt, AllocError> {
+        let memory_limit = self.memory_limit();
+        let enforced = memory_limit.is_some() && policy != LimitPolicy::FallbackToGlobal;
+        let minimum = self.data.config.minimum_allocation_size;
+        let size_class = (self.data.config.slab_layout == SlabLayout::SizeClasses)
+            .then(|| size_class(length.div_ceil(minimum)));
+        let stripes = size_class.unwrap_or_else(|| length.div_ceil(minimum));
+        // With its guard stripe or once rounded up to its size class, an
+        // allocation may be too large for a slab even if its length is not.
+        if length >= self.maximum_allocation_size()
+            || (stripes + GUARD_STRIPES) * minimum > self.slab_size()
+        {
+            return self.attempt_oversized(length, memory_limit, enforced);
+        }
+
+        if let Some(cache) = &self.data.thread_cache {
+            if let Some(allocation) = cache.take(length) {
+                return Ok(Attempt::Allocated(allocation));
+            }
+        }
+
+        loop {
+            // Try to allocate in all existing slabs, starting with the slab
+            // this thread last allocated from.
+            let slabs = self.iter();
+            let searched = slabs.len;
+            let mut contended = Vec::new();
+            for (index, slab) in slabs {
+                if slab.size_class() != size_class {
+                    continue;
+                }
+                match slab.allocate(length) {
+                    Ok(allocation) => {
+                        self.set_home_slab(index);
+                        return Ok(Attempt::Allocated(allocation));
+                    }
+                    Err(Unavailable::Contended) => contended.push((index, slab)),
+                    Err(Unavailable::Full) => {}
+                }
+            }
+
+            // Slabs in use by other threads may still have room. Give those
+            // threads a chance to finish before concluding that the slabs are
+            // full.
+            for _ in 0..CONTENDED_RETRIES {
+                if contended.is_empty() {
+                    break;
+                }
+                thread::yield_now();
+                if let Some(allocation) = self.retry_contended(&mut contended, length) {
+                    return Ok(Attempt::Allocated(allocation));
                 }
             }
 
             // No current slabs had any space available. Allocate a new slab if
             // we aren't at our memory limit.
-            loop {
-                let new_slab = self.new_slab();
-                if let Some(new_slab) = new_slab {
-                    if let Some(allocation) = new_slab.allocate(length) {
-                        return Some(allocation);
+            match self.new_slab(searched, size_class, stripes) {
+                Ok(NewSlab::Created(index, new_slab)) => {
+                    if !contended.is_empty() {
+                        self.data.contended_slabs.fetch_add(1, Ordering::Relaxed);
                     }
-                } else {
-                    // At the memory limit, fall back to the global allocator
-                    break;
+                    if let Ok(allocation) = new_slab.allocate(length) {
+                        self.set_home_slab(index);
+                        return Ok(Attempt::Allocated(allocation));
+                    }
+                }
+                // Another thread added a slab since the search began.
+                Ok(NewSlab::Raced) => {}
+                Ok(NewSlab::TooSmall) => {
+                    return self.attempt_oversized(length, memory_limit, enforced);
+                }
+                Ok(NewSlab::AtLimit) => {
+                    if !enforced {
+                        return self.attempt_global(length, false);
+                    }
+
+                    // Slabs that were in use by other threads were skipped
+                    // above. Wait for them before giving up.
+                    for (_, slab) in self.iter() {
+                        if slab.size_class() != size_class {
+                            continue;
+                        }
+                        if let Some(allocation) = slab.allocate_waiting(length) {
+                            return Ok(Attempt::Allocated(allocation));
+                        }
+                    }
+                    return Ok(Attempt::AtLimit);
+                }
+                Err(err) => {
+                    // Out of memory. Unless the limit is enforced, fall back
+                    // to the global allocator.
+                    if enforced {
+                        return Err(err);
+                    }
+                    return self.attempt_global(length, false);
                 }
             }
         }
+    }
+
+    /// Attempts to allocate `length` bytes that do not fit in a slab.
+    fn attempt_oversized(
+        &self,
+        length: usize,
+        memory_limit: Option<usize>,
+        enforced: bool,
+    ) -> Result<Attempt, AllocError> {
+        if enforced
+            && (self.data.config.limit_scope == LimitScope::Slabs
+                || memory_limit.is_some_and(|limit| length > limit))
+        {
+            return Err(self.limit_exceeded());
+        }
+        self.attempt_global(length, enforced)
+    }
 
+    /// Attempts to allocate `length` bytes from the slabs that were in use by
+    /// other threads, removing the slabs that turn out to be full.
+    fn retry_contended(
+        &self,
+        contended: &mut Vec<(usize, &Slab)>,
+        length: usize,
+    ) -> Option<Allocation> {
+        let mut retry = 0;
+        while let Some((index, slab)) = contended.get(retry) {
+            match slab.allocate(length) {
+                Ok(allocation) => {
+                    self.set_home_slab(*index);
+                    return Some(allocation);
+                }
+                Err(Unavailable::Contended) => retry += 1,
+                Err(Unavailable::Full) => {
+                    contended.swap_remove(retry);
+                }
+            }
+        }
         None
     }
 
-    pub fn new_slab(&self) -> Option<Slab> {
-        let mut entries = self.data.entries.write();
-        if self.data.config.memory_limit.map_or(true, |limit| {
-            entries.len() * self.data.config.slab_size < limit
-        }) {
-            let slab = Slab::new(
-                self.data.config.slab_size,
-                self.data.layout,
-                self.data.config.minimum_allocation_size,
-            );
-            entries.push(slab.clone());
-            Some(slab)
-        } else {
-            None
+    /// Attempts to allocate `length` bytes using the global allocator,
+    /// accounting for the allocation in this ring.
+    fn attempt_global(&self, length: usize, enforced: bool) -> Result<Attempt, AllocError> {
+        // Reserving an impossible length would briefly wrap the total bytes
+        // seen by other threads.
+        Layout::array::<u8>(length).map_err(|_| AllocError::CapacityOverflow)?;
+        if !self.reserve(length, enforced) {
+            return Ok(Attempt::AtLimit);
+        }
+        match Allocation::try_global(length) {
+            Ok(allocation) => {
+                self.data.global_bytes.fetch_add(length, Ordering::Relaxed);
+                self.data.global_allocations.fetch_add(1, Ordering::Relaxed);
+                Ok(Attempt::Allocated(allocation.account(self.clone())))
+            }
+            Err(err) => {
+                self.data.total_bytes.fetch_sub(length, Ordering::SeqCst);
+                Err(err)
+            }
+        }
+    }
+
+    /// Adds `bytes` to the total bytes in use. When `enforced` and the limit
+    /// covers the total, returns false instead if the limit would be exceeded.
+    fn reserve(&self, bytes: usize, enforced: bool) -> bool {
+        match self.memory_limit() {
+            Some(limit) if enforced && self.data.config.limit_scope == LimitScope::Total => self
+                .data
+                .total_bytes
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |total| {
+                    total.checked_add(bytes).filter(|total| *total <= limit)
+                })
+                .is_ok(),
+            _ => {
+                self.data.total_bytes.fetch_add(bytes, Ordering::SeqCst);
+                true
+            }
+        }
+    }
+
+    /// Releases the accounting of a global allocation of `bytes`.
+    pub fn release_global(&self, bytes: usize) {
+        self.data.global_allocations.fetch_sub(1, Ordering::Relaxed);
+        self.release_global_bytes(bytes);
+    }
+
+    /// Releases the accounting of `bytes` of a global allocation that
+    /// shrank.
+    pub fn release_global_bytes(&self, bytes: usize) {
+        self.data.global_bytes.fetch_sub(bytes, Ordering::Relaxed);
+        self.data.total_bytes.fetch_sub(bytes, Ordering::SeqCst);
+        self.data.notifier.notify_freed();
+    }
+
+    /// Returns the allocations in the current thread's cache to their slabs.
+    pub fn flush_thread_cache(&self) {
+        if let Some(cache) = &self.data.thread_cache {
+            cache.flush();
+        }
+    }
+
+    fn limit_exceeded(&self) -> AllocError {
+        AllocError::MemoryLimitExceeded {
+            limit: self.memory_limit().unwrap_or_default(),
+        }
+    }
+
+    /// Adds a new slab serving `size_class` unless the memory limit has been
+    /// reached, slabs were added after the first `searched` slabs were
+    /// searched, or the slab would be too small for an allocation of
+    /// `stripes` stripes.
+    fn new_slab(
+        &self,
+        searched: usize,
+        size_class: Option<usize>,
+        stripes: usize,
+    ) -> Result<NewSlab<'_>, AllocError> {
+        let config = &self.data.config;
+        let slabs = &self.data.slabs;
+        let _growing = self.data.growing.lock();
+        if slabs.len() > searched {
+            return Ok(NewSlab::Raced);
+        }
+        let slab_size = self.slab_size();
+        if (stripes + GUARD_STRIPES) * config.minimum_allocation_size > slab_size {
+            return Ok(NewSlab::TooSmall);
+        }
+        let within_limit = match (self.memory_limit(), config.limit_scope) {
+            (Some(limit), LimitScope::Slabs) => {
+                slabs.iter().map(Slab::size).sum::<usize>() + slab_size <= limit
+            }
+            _ => true,
+        };
+        if !within_limit || !self.reserve(slab_size, true) {
+            return Ok(NewSlab::AtLimit);
+        }
+
+        let layout = Layout::array::<u8>(slab_size).map_err(|_| AllocError::CapacityOverflow)?;
+        let Some(slab) = Slab::new(
+            slab_size,
+            layout,
+            config.minimum_allocation_size,
+            config.scrub_policy,
+            self.data.notifier.clone(),
+            self.data.thread_cache.clone(),
+            size_class.map_or_else(
+                || Placement::Spans(config.placement_strategy.clone()),
+                Placement::SizeClass,
+            ),
+        ) else {
+            self.data.total_bytes.fetch_sub(slab_size, Ordering::SeqCst);
+            return Err(AllocError::OutOfMemory { layout });
+        };
+        let (index, slab) = slabs.push(slab);
+        Ok(NewSlab::Created(index, slab))
+    }
+
+    /// Returns statistics about the memory in use.
+    pub fn statistics(&self) -> Statistics {
+        let slabs = &self.data.slabs;
+        Statistics {
+            slabs: slabs.len(),
+            slab_bytes: slabs.iter().map(Slab::size).sum(),
+            free_slab_bytes: slabs.iter().map(Slab::free_bytes).sum(),
+            global_allocations: self.data.global_allocations.load(Ordering::Relaxed),
+            global_bytes: self.data.global_bytes.load(Ordering::Relaxed),
+            contended_slabs: self.data.contended_slabs.load(Ordering::Relaxed),
         }
     }
 
+    /// Returns an iterator over the slabs that exist when it is created and
+    /// their indices, starting at the current thread's home slab. Threads
+    /// without a home slab start at a different slab each time to spread
+    /// allocations across the slabs.
     pub fn iter(&self) -> SlabRingIter<'_> {
-        let entries = self.data.entries.read();
-        let start = if entries.is_empty() {
+        // Slabs are never removed, so the slabs below the current length can
+        // be searched without locking, even while new slabs are added.
+        let slabs = &self.data.slabs;
+        let len = slabs.len();
+        let start = if len == 0 {
             0
         } else {
-            // Start iterating at the previous slot
-            loop {
-                let current_cycle = self.data.cycle.load(Ordering::Acquire);
-                let next_cycle = current_cycle.checked_sub(1).unwrap_or(entries.len() - 1);
-                if self
-                    .data
-                    .cycle
-                    .compare_exchange(
-                        current_cycle,
-                        next_cycle,
-                        Ordering::Release,
-                        Ordering::Relaxed,
-                    )
-                    .is_ok()
-                {
-                    break next_cycle;
-                }
-            }
+            self.home_slab()
+                .unwrap_or_else(|| self.data.cycle.fetch_add(1, Ordering::Relaxed))
+                % len
         };
         SlabRingIter {
-            entries,
+            slabs,
+            len,
             start,
             position: None,
         }
     }
+
+    /// Returns the index of the slab the current thread last allocated from
+    /// in this ring.
+    fn home_slab(&self) -> Option<usize> {
+        HOME_SLABS.with(|homes| {
+            homes[self.data.id % HOME_SLAB_ENTRIES]
+                .get()
+                .and_then(|(ring, index)| (ring == self.data.id).then_some(index))
+        })
+    }
+
+    fn set_home_slab(&self, index: usize) {
+        HOME_SLABS.with(|homes| {
+            homes[self.data.id % HOME_SLAB_ENTRIES].set(Some((self.data.id, index)));
+        });
+    }
+}
+
+/// An allocation waiting for memory to be freed. Its waker is deregistered
+/// when it is dropped.
+pub struct PendingAllocation<'a> {
+    ring: &'a SlabRing,
+    length: usize,
+    registration: Option<u64>,
+}
+
+impl Future for PendingAllocation<'_> {
+    type Output = Result<Allocation, AllocError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let notifier = &this.ring.data.notifier;
+        loop {
+            let generation = notifier.generation();
+            match this.ring.attempt(this.length, LimitPolicy::Block) {
+                Ok(Attempt::Allocated(allocation)) => return Poll::Ready(Ok(allocation)),
+                Ok(Attempt::AtLimit) => {
+                    if notifier.register(generation, cx.waker(), &mut this.registration) {
+                        return Poll::Pending;
+                    }
+                }
+                Err(err) => return Poll::Ready(Err(err)),
+            }
+        }
+    }
+}
+
+impl Drop for PendingAllocation<'_> {
+    fn drop(&mut self) {
+        if let Some(registration) = self.registration {
+            self.ring.data.notifier.deregister(registration);
+        }
+    }
 }
 
 pub struct SlabRingIter<'a> {
-    entries: RwLockReadGuard<'a, Vec<Slab>>,
+    slabs: &'a SlabList,
+    len: usize,
     start: usize,
     position: Option<usize>,
 }
 
 impl<'a> Iterator for SlabRingIter<'a> {
-    type Item = Slab;
+    type Item = (usize, &'a Slab);
 
     fn next(&mut self) -> Option<Self::Item> {
-        match self.position {
-            Some(position) => {
-                // Cycle through the entries
-                let next = match position + 1 {
-                    next if next == self.entries.len() => 0,
-                    next => next,
-                };
-                if next == self.start {
-                    // Full cycle
-                    None
-                } else {
-                    self.position = Some(next);
-                    Some(self.entries[next].clone())
-                }
-            }
-            None => {
-                if self.entries.is_empty() {
-                    None
-                } else {
-                    self.position = Some(self.start);
-                    Some(self.entries[self.start].clone())
-                }
-            }
+        let next = match self.position {
+            // Cycle through the slabs
+            Some(position) => match position + 1 {
+                next if next == self.len => 0,
+                next => next,
+            },
+            None if self.len == 0 => return None,
+            None => self.start,
+        };
+        if self.position.is_some() && next == self.start {
+            // Full cycle
+            return None;
         }
+        self.position = Some(next);
+        self.slabs.get(next).map(|slab| (next, slab))
     }
 }
@@ -0,0 +1,30 @@
+/// Statistics about the memory used by an [`Allocator`](crate::Allocator),
+/// returned from [`Allocator::statistics()`](crate::Allocator::statistics).
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default)]
+pub struct Statistics {
+    /// The number of slabs that have been allocated.
+    pub slabs: usize,
+    /// The total size of all slabs, in bytes.
+    pub slab_bytes: usize,
+    /// The number of bytes within slabs that are not allocated.
+    pub free_slab_bytes: usize,
+    /// The number of live allocations made using the global allocator.
+    pub global_allocations: usize,
+    /// The total size of the live allocations made using the global
+    /// allocator, in bytes.
+    pub global_bytes: usize,
+    /// The number of slabs that were added while other slabs were in use by
+    /// other threads and may have had enough free space. A high number
+    /// indicates that memory grew because of lock contention rather than
+    /// load.
+    pub contended_slabs: usize,
+}
+
+impl Statistics {
+    /// Returns the total number of bytes in use: the size of all slabs plus
+    /// the size of the allocations made using the global allocator.
+    #[must_use]
+    pub const fn total_bytes(&self) -> usize {
+        self.slab_bytes + self.global_bytes
+    }
+}
@@ -0,0 +1,225 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Weak,
+    },
+};
+
+use crate::{
+    slab::{size_class, Slab},
+    Allocation,
+};
+
+thread_local! {
+    static MAGAZINES: RefCell<Magazines> = RefCell::default();
+}
+
+/// A handle to an allocator's thread-local caches of freed slab allocations.
+///
+/// Each thread keeps up to `capacity` freed allocations per size class, and
+/// at most `maximum_bytes` in total, which tracks the allocator's current
+/// slab size. They are returned to their slabs when
+/// the cache overflows, when [`ThreadCache::flush()`] is called, or when the
+/// thread exits. Allocations cached for an allocator that has been dropped
+/// are returned the next time the thread uses any thread cache.
+#[derive(Debug, Clone)]
+pub struct ThreadCache {
+    /// The allocator's state, which only needs to be alive while its
+    /// allocations are cached.
+    owner: Weak<dyn Send + Sync>,
+    capacity: usize,
+    maximum_bytes: Arc<AtomicUsize>,
+    minimum_allocation_size: usize,
+}
+
+impl ThreadCache {
+    /// Returns a new cache for `owner`, or `None` if `capacity` is 0.
+    pub fn new(
+        owner: Weak<dyn Send + Sync>,
+        capacity: usize,
+        maximum_bytes: Arc<AtomicUsize>,
+        minimum_allocation_size: usize,
+    ) -> Option<Self> {
+        (capacity > 0).then(|| Self {
+            owner,
+            capacity,
+            maximum_bytes,
+            minimum_allocation_size,
+        })
+    }
+
+    fn size_class(&self, size: usize) -> usize {
+        size_class(size.div_ceil(self.minimum_allocation_size))
+    }
+
+    /// Returns the most recently cached allocation of at least `length`
+    /// bytes in the size class of `length` from the current thread's cache.
+    pub fn take(&self, length: usize) -> Option<Allocation> {
+        let class = self.size_class(length);
+        let (dead, cached) = MAGAZINES
+            .try_with(|magazines| {
+                let mut magazines = magazines.try_borrow_mut().ok()?;
+                let dead = magazines.remove_dead();
+                let cached = magazines.get(&self.owner).and_then(|cache| {
+                    let magazine = cache.classes.get_mut(&class)?;
+                    let index = magazine.iter().rposition(|cached| cached.size >= length)?;
+                    let cached = magazine.remove(index);
+                    cache.bytes -= cached.size;
+                    Some(cached)
+                });
+                Some((dead, cached))
+            })
+            .ok()??;
+        dead.into_iter().for_each(CachedAllocations::release_all);
+        cached.map(|cached| cached.slab.reuse(cached.bytes, cached.size))
+    }
+
+    /// Stores the freed allocation at `bytes` in the current thread's cache.
+    /// Returns false if the allocation could not be cached.
+    pub fn put(&self, slab: &Slab, bytes: *mut u8, size: usize) -> bool {
+        if self.owner.strong_count() == 0 {
+            return false;
+        }
+        let class = self.size_class(size);
+        let result = MAGAZINES.try_with(|magazines| {
+            let mut magazines = magazines.try_borrow_mut().ok()?;
+            let dead = magazines.remove_dead();
+            let cache = magazines.get_or_insert(&self.owner);
+            let magazine = cache.classes.entry(class).or_default();
+            let overflow = if magazine.len() == self.capacity {
+                // Return the least recently freed half to the slabs.
+                magazine
+                    .drain(..self.capacity.div_ceil(2))
+                    .collect::<Vec<_>>()
+            } else {
+                Vec::new()
+            };
+            cache.bytes -= overflow.iter().map(|cached| cached.size).sum::<usize>();
+            let cached = cache.bytes + size <= self.maximum_bytes.load(Ordering::Relaxed);
+            if cached {
+                magazine.push(Cached {
+                    slab: slab.clone(),
+                    bytes,
+                    size,
+                });
+                cache.bytes += size;
+            }
+            Some((dead, overflow, cached))
+        });
+        match result {
+            Ok(Some((dead, overflow, cached))) => {
+                // The overflow is released after the cache is no longer
+                // borrowed.
+                dead.into_iter().for_each(CachedAllocations::release_all);
+                overflow.into_iter().for_each(Cached::release);
+                cached
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns all allocations in the current thread's cache to their slabs.
+    pub fn flush(&self) {
+        let cache = MAGAZINES
+            .try_with(|magazines| {
+                let mut magazines = magazines.try_borrow_mut().ok()?;
+                magazines.remove(&self.owner)
+            })
+            .ok()
+            .flatten();
+        if let Some(cache) = cache {
+            cache.release_all();
+        }
+    }
+}
+
+/// The current thread's caches, keyed by the [`ThreadCache`] owner.
+#[derive(Default)]
+struct Magazines {
+    caches: Vec<(Weak<dyn Send + Sync>, CachedAllocations)>,
+}
+
+impl Magazines {
+    fn position(&self, owner: &Weak<dyn Send + Sync>) -> Option<usize> {
+        self.caches
+            .iter()
+            .position(|(cache_owner, _)| cache_owner.ptr_eq(owner))
+    }
+
+    fn get(&mut self, owner: &Weak<dyn Send + Sync>) -> Option<&mut CachedAllocations> {
+        let index = self.position(owner)?;
+        Some(&mut self.caches[index].1)
+    }
+
+    fn get_or_insert(&mut self, owner: &Weak<dyn Send + Sync>) -> &mut CachedAllocations {
+        let index = self.position(owner).unwrap_or_else(|| {
+            self.caches
+                .push((owner.clone(), CachedAllocations::default()));
+            self.caches.len() - 1
+        });
+        &mut self.caches[index].1
+    }
+
+    fn remove(&mut self, owner: &Weak<dyn Send + Sync>) -> Option<CachedAllocations> {
+        let index = self.position(owner)?;
+        Some(self.caches.swap_remove(index).1)
+    }
+
+    /// Removes the caches of allocators that have been dropped, so that the
+    /// slabs they hold can be freed.
+    fn remove_dead(&mut self) -> Vec<CachedAllocations> {
+        let mut dead = Vec::new();
+        let mut index = 0;
+        while index < self.caches.len() {
+            if self.caches[index].0.strong_count() == 0 {
+                dead.push(self.caches.swap_remove(index).1);
+            } else {
+                index += 1;
+            }
+        }
+        dead
+    }
+}
+
+impl Drop for Magazines {
+    fn drop(&mut self) {
+        // The thread is exiting.
+        for (_, cache) in self.caches.drain(..) {
+            cache.release_all();
+        }
+    }
+}
+
+/// The allocations one thread has cached for one allocator.
+#[derive(Default)]
+struct CachedAllocations {
+    /// The cached allocations, keyed by size class.
+    classes: HashMap<usize, Vec<Cached>>,
+    /// The total size of the cached allocations.
+    bytes: usize,
+}
+
+impl CachedAllocations {
+    fn release_all(self) {
+        self.classes
+            .into_values()
+            .flatten()
+            .for_each(Cached::release);
+    }
+}
+
+/// A freed allocation held by a thread's cache.
+struct Cached {
+    slab: Slab,
+    bytes: *mut u8,
+    size: usize,
+}
+
+impl Cached {
+    /// Returns this allocation to its slab.
+    fn release(self) {
+        self.slab.uncache(self.bytes, self.size);
+    }
+}
@@ -0,0 +1,213 @@
+use std::{
+    backtrace::Backtrace,
+    collections::HashMap,
+    fmt::{self, Display},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    thread::{self, ThreadId},
+};
+
+use parking_lot::Mutex;
+
+/// Controls whether an [`Allocator`](crate::Allocator) records the
+/// allocations it hands out.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default)]
+pub enum AllocationTracking {
+    /// Allocations are not recorded.
+    #[default]
+    Disabled,
+    /// Every live allocation is recorded.
+    Enabled,
+    /// Every live allocation is recorded along with a backtrace captured when
+    /// it was allocated.
+    WithBacktraces,
+}
+
+/// A record of an allocation that has not been freed, returned from
+/// [`Allocator::outstanding_allocations()`](crate::Allocator::outstanding_allocations).
+#[derive(Debug, Clone)]
+pub struct OutstandingAllocation {
+    /// A unique identifier for this allocation within its allocator.
+    pub id: u64,
+    /// The current size of the allocation, in bytes.
+    pub size: usize,
+    /// The id of the slab the allocation is from, or `None` if it is from the
+    /// global allocator.
+    pub slab: Option<usize>,
+    /// The thread that created the allocation.
+    pub thread: ThreadId,
+    /// The name of the thread that created the allocation, if it was named.
+    pub thread_name: Option<String>,
+    /// The backtrace of the allocation's creation, if the allocator is
+    /// configured with [`AllocationTracking::WithBacktraces`].
+    pub backtrace: Option<Arc<Backtrace>>,
+}
+
+impl Display for OutstandingAllocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "allocation {}: {} bytes ", self.id, self.size)?;
+        match self.slab {
+            Some(slab) => write!(f, "in slab {slab}")?,
+            None => f.write_str("from the global allocator")?,
+        }
+        match &self.thread_name {
+            Some(name) => write!(f, ", created on thread '{name}' ({:?})", self.thread)?,
+            None => write!(f, ", created on {:?}", self.thread)?,
+        }
+        if let Some(backtrace) = &self.backtrace {
+            write!(f, "\n{backtrace}")?;
+        }
+        Ok(())
+    }
+}
+
+/// A report of the allocations that have not been freed, returned from
+/// [`Allocator::leak_report()`](crate::Allocator::leak_report).
+#[derive(Debug, Clone, Default)]
+pub struct LeakReport {
+    /// The outstanding allocations, ordered by their creation.
+    pub allocations: Vec<OutstandingAllocation>,
+}
+
+impl LeakReport {
+    /// Returns true if there are no outstanding allocations.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.allocations.is_empty()
+    }
+
+    /// Returns the total number of bytes held by the outstanding allocations.
+    #[must_use]
+    pub fn total_bytes(&self) -> usize {
+        self.allocations
+            .iter()
+            .map(|allocation| allocation.size)
+            .sum()
+    }
+}
+
+impl Display for LeakReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} outstanding allocations totaling {} bytes",
+            self.allocations.len(),
+            self.total_bytes()
+        )?;
+        for allocation in &self.allocations {
+            write!(f, "\n{allocation}")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct Tracker {
+    capture_backtraces: bool,
+    next_id: AtomicU64,
+    allocations: Mutex<HashMap<u64, OutstandingAllocation>>,
+}
+
+impl Tracker {
+    pub fn new(tracking: AllocationTracking) -> Option<Arc<Self>> {
+        match tracking {
+            AllocationTracking::Disabled => None,
+            AllocationTracking::Enabled | AllocationTracking::WithBacktraces => {
+                Some(Arc::new(Self {
+                    capture_backtraces: tracking == AllocationTracking::WithBacktraces,
+                    next_id: AtomicU64::new(0),
+                    allocations: Mutex::default(),
+                }))
+            }
+        }
+    }
+
+    pub fn track(self: &Arc<Self>, size: usize, slab: Option<usize>) -> TrackingHandle {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let thread = thread::current();
+        let backtrace = self
+            .capture_backtraces
+            .then(|| Arc::new(Backtrace::force_capture()));
+        self.allocations.lock().insert(
+            id,
+            OutstandingAllocation {
+                id,
+                size,
+                slab,
+                thread: thread.id(),
+                thread_name: thread.name().map(String::from),
+                backtrace,
+            },
+        );
+        TrackingHandle {
+            tracker: self.clone(),
+            id,
+        }
+    }
+
+    pub fn outstanding_allocations(&self) -> Vec<OutstandingAllocation> {
+        let mut allocations = self
+            .allocations
+            .lock()
+            .values()
+            .cloned()
+            .collect::<Vec<_>>();
+        allocations.sort_unstable_by_key(|allocation| allocation.id);
+        allocations
+    }
+}
+
+/// Removes an allocation's record from its [`Tracker`] when dropped.
+#[derive(Debug)]
+pub struct TrackingHandle {
+    tracker: Arc<Tracker>,
+    id: u64,
+}
+
+impl TrackingHandle {
+    pub fn resize(&self, size: usize) {
+        if let Some(allocation) = self.tracker.allocations.lock().get_mut(&self.id) {
+            allocation.size = size;
+        }
+    }
+}
+
+impl Drop for TrackingHandle {
+    fn drop(&mut self) {
+        self.tracker.allocations.lock().remove(&self.id);
+    }
+}
+
+#[test]
+fn leak_report() {
+    use crate::{Allocator, Buffer};
+
+    let allocator = Allocator::build()
+        .allocation_tracking(AllocationTracking::Enabled)
+        .finish()
+        .unwrap();
+    let mut kept = Buffer::with_capacity(64, allocator.clone());
+    {
+        let _scoped = Buffer::with_capacity(128, allocator.clone());
+        let _large = allocator.allocate(1024 * 1024);
+        assert_eq!(allocator.outstanding_allocations().len(), 3);
+    }
+
+    let outstanding = allocator.outstanding_allocations();
+    assert_eq!(outstanding.len(), 1);
+    assert_eq!(outstanding[0].size, 64);
+    assert!(outstanding[0].slab.is_some());
+    assert_eq!(outstanding[0].thread, thread::current().id());
+
+    kept.shrink_to(16);
+    let report = allocator.leak_report();
+    assert_eq!(report.total_bytes(), 16);
+    assert!(report
+        .to_string()
+        .starts_with("1 outstanding allocations totaling 16 bytes"));
+
+    drop(kept);
+    assert!(allocator.leak_report().is_empty());
+}